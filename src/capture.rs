@@ -0,0 +1,153 @@
+use std::collections::HashSet;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use gtk::glib;
+use gtk::prelude::*;
+use pnet::datalink::DataLinkReceiver;
+
+use crate::channel;
+use crate::dissect;
+use crate::widgets::parse_entry;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Flow {
+    source: Ipv4Addr,
+    destination: Ipv4Addr,
+    protocol: u8,
+    source_port: u16,
+    destination_port: u16
+}
+impl Flow {
+    pub(crate) fn from_frame(frame: &[u8]) -> Option<Flow> {
+        Self::from_ip(dissect::ipv4_payload(frame)?)
+    }
+
+    /* Only the first eight transport bytes are used, which is what ICMP errors are guaranteed to quote.
+     * ICMP echo messages use their identifier in place of both ports. */
+    fn from_ip(data: &[u8]) -> Option<Flow> {
+        if data.len() < 20 || data[0] >> 4 != 4 { return None; }
+        let transport = data.get((data[0] & 0x0f) as usize * 4..)?;
+        let port = |offset: usize| transport.get(offset..offset + 2).map(|v| u16::from_be_bytes([v[0], v[1]])).unwrap_or(0);
+
+        let (source_port, destination_port) = match data[9] {
+            6 | 17 | 132 => (port(0), port(2)),
+            1 if matches!(transport.first(), Some(0) | Some(8)) => (port(4), port(4)),
+            _ => (0, 0)
+        };
+
+        Some(Flow {
+            source: Ipv4Addr::new(data[12], data[13], data[14], data[15]),
+            destination: Ipv4Addr::new(data[16], data[17], data[18], data[19]),
+            protocol: data[9],
+            source_port, destination_port
+        })
+    }
+    fn reversed(&self) -> Flow {
+        Flow {
+            source: self.destination,
+            destination: self.source,
+            protocol: self.protocol,
+            source_port: self.destination_port,
+            destination_port: self.source_port
+        }
+    }
+}
+
+fn is_response(frame: &[u8], flows: &HashSet<Flow>) -> bool {
+    let flow = match Flow::from_frame(frame) {
+        Some(flow) => flow,
+        None => return false
+    };
+    if flows.contains(&flow.reversed()) { return true; }
+
+    /* ICMP errors quoting one of our packets. */
+    let ip = match dissect::ipv4_payload(frame) {
+        Some(ip) => ip,
+        None => return false
+    };
+    let icmp = match ip.get((ip[0] & 0x0f) as usize * 4..) {
+        Some(icmp) if flow.protocol == 1 && icmp.len() > 8 => icmp,
+        _ => return false
+    };
+    matches!(icmp[0], 3 | 4 | 5 | 11 | 12) && Flow::from_ip(&icmp[8..]).is_some_and(|quoted| flows.contains(&quoted))
+}
+
+pub(crate) struct CapturePane {
+    enabled: gtk::CheckButton,
+    duration: gtk::Entry,
+    list: gtk::ListBox
+}
+impl CapturePane {
+    pub(crate) fn new() -> Self {
+        Self {
+            enabled: gtk::CheckButton::with_label("Capture responses"),
+            duration: gtk::Entry::builder().placeholder_text("Capture time after sending, ms (2000)").build(),
+            list: gtk::ListBox::builder().selection_mode(gtk::SelectionMode::None).build()
+        }
+    }
+
+    pub(crate) fn prepare_controls(&self) -> gtk::Box {
+        let controls = gtk::Box::builder().orientation(gtk::Orientation::Vertical).valign(gtk::Align::Center).spacing(6).build();
+        controls.append(&self.enabled);
+        controls.append(&self.duration);
+
+        let clear = gtk::Button::with_label("Clear responses");
+        let list = self.list.clone();
+        clear.connect_clicked(move |_| {
+            while let Some(row) = list.first_child() { list.remove(&row); }
+        });
+        controls.append(&clear);
+
+        controls
+    }
+    pub(crate) fn prepare_list(&self) -> gtk::Frame {
+        let scrolled_window = gtk::ScrolledWindow::builder().hscrollbar_policy(gtk::PolicyType::Automatic)
+            .child(&self.list).min_content_height(120).build();
+        gtk::Frame::builder().label("Captured responses").child(&scrolled_window).build()
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool { self.enabled.is_active() }
+
+    /* Collects responses to the given sent frames in a worker thread. The receiver has to be opened before
+     * sending, so that nothing arriving in between is lost. A timed run is captured for as long as its sending
+     * flag stays set, and for the capture time after that. */
+    pub(crate) fn start<T: AsRef<[u8]>>(&self, mut rx: Box<dyn DataLinkReceiver>, sent: &[T], sending: Option<Arc<AtomicBool>>) {
+        let duration = match parse_entry(&self.duration, 2000u64, "capture time") {
+            Some(value) => Duration::from_millis(value),
+            None => return
+        };
+        let flows: HashSet<Flow> = sent.iter().filter_map(|frame| Flow::from_frame(frame.as_ref())).collect();
+
+        let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        thread::spawn(move || {
+            let started = Instant::now();
+            let mut handle = |frame: &[u8]| {
+                if is_response(frame, &flows) {
+                    let _ = sender.send(format!("{:.6}  {}", started.elapsed().as_secs_f64(), dissect::summary(frame)));
+                }
+                false
+            };
+            let mut result = Ok(());
+            while result.is_ok() && sending.as_ref().is_some_and(|sending| sending.load(Ordering::Relaxed)) {
+                result = channel::receive_until(&mut rx, Instant::now() + channel::POLL_INTERVAL, &mut handle);
+            }
+            if result.is_ok() { result = channel::receive_until(&mut rx, Instant::now() + duration, &mut handle); }
+            match result {
+                Ok(_) => { let _ = sender.send(format!("Capture finished after {} ms", started.elapsed().as_millis())); },
+                Err(what) => { let _ = sender.send(what); }
+            }
+        });
+
+        let list = self.list.clone();
+        receiver.attach(None, move |line: String| {
+            list.append(&gtk::Label::builder().label(&line).halign(gtk::Align::Start).selectable(true).build());
+            glib::Continue(true)
+        });
+    }
+}
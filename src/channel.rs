@@ -0,0 +1,76 @@
+use std::io;
+use std::io::ErrorKind;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use pnet::datalink;
+use pnet::datalink::Channel::Ethernet;
+use pnet::datalink::DataLinkSender;
+use pnet::datalink::DataLinkReceiver;
+use pnet::datalink::NetworkInterface;
+
+use crate::pcap::PcapSender;
+
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+pub(crate) type Channel = (Box<dyn DataLinkSender>, Box<dyn DataLinkReceiver>);
+
+/* Pseudo-interfaces: "pcap:PATH" writes the frames to a pcap file and "null" drops them. Neither needs a raw
+ * socket, so both also work in offline mode, and nothing is ever received on them. */
+pub(crate) const PCAP_PREFIX: &str = "pcap:";
+pub(crate) const NULL_INTERFACE: &str = "null";
+
+struct NullSender;
+impl DataLinkSender for NullSender {
+    fn build_and_send(&mut self, num_packets: usize, packet_size: usize, func: &mut dyn FnMut(&mut [u8])) -> Option<io::Result<()>> {
+        let mut packet = vec![0; packet_size];
+        for _ in 0..num_packets { func(&mut packet); }
+        Some(Ok(()))
+    }
+    fn send_to(&mut self, _packet: &[u8], _dst: Option<NetworkInterface>) -> Option<io::Result<()>> { Some(Ok(())) }
+}
+
+struct SilentReceiver {
+    read_timeout: Duration
+}
+impl DataLinkReceiver for SilentReceiver {
+    fn next(&mut self) -> io::Result<&[u8]> {
+        thread::sleep(self.read_timeout);
+        Err(io::Error::new(ErrorKind::TimedOut, "Nothing is received on a pseudo-interface"))
+    }
+}
+
+/* Offline, packets are only built and queued and no raw socket is ever opened. */
+pub(crate) fn open(iface: &str, offline: bool, read_timeout: Option<Duration>) -> Result<Channel, String> {
+    let silent = Box::new(SilentReceiver { read_timeout: read_timeout.unwrap_or(POLL_INTERVAL) });
+    if let Some(path) = iface.strip_prefix(PCAP_PREFIX) {
+        if path.is_empty() { return Err("Enter the path of the pcap file to write.".to_string()); }
+        return Ok((Box::new(PcapSender::open(path)?), silent));
+    }
+    if iface == NULL_INTERFACE { return Ok((Box::new(NullSender), silent)); }
+
+    if offline { return Err("Offline mode is on, nothing can be sent.".to_string()); }
+
+    let interface = match datalink::interfaces().into_iter().find(|interface: &NetworkInterface| interface.name == iface) {
+        Some(interface) => interface,
+        None => return Err("Unknown network interface ".to_owned() + iface)
+    };
+
+    let config = datalink::Config { read_timeout, ..Default::default() };
+    match datalink::channel(&interface, config) {
+        Ok(Ethernet(tx, rx)) => Ok((tx, rx)),
+        Ok(_) => Err("Unhandled channel type.".to_string()),
+        Err(what) => Err("Failed to create datalink channel: ".to_owned() + &what.to_string())
+    }
+}
+
+pub(crate) fn receive_until(rx: &mut Box<dyn DataLinkReceiver>, deadline: Instant, mut handle: impl FnMut(&[u8]) -> bool) -> Result<(), String> {
+    while Instant::now() < deadline {
+        match rx.next() {
+            Ok(frame) => if handle(frame) { return Ok(()) },
+            Err(what) if matches!(what.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock | ErrorKind::Interrupted) => {},
+            Err(what) => return Err("Failed to receive packet: ".to_owned() + &what.to_string())
+        }
+    }
+    Ok(())
+}
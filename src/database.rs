@@ -1,20 +1,37 @@
 use std::cell::RefCell;
-use std::ops::RangeInclusive;
 use std::rc::Rc;
-use gtk::prelude::{BoxExt, ButtonExt, DialogExt, EditableExt, GtkWindowExt, WidgetExt};
-use pnet::datalink;
-use pnet::datalink::Channel::Ethernet;
-use pnet::datalink::NetworkInterface;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use gtk::gdk;
+use gtk::glib;
+use gtk::prelude::{BoxExt, ButtonExt, Cast, CheckButtonExt, DialogExt, EditableExt, EntryExt, GridExt, GtkWindowExt, StaticType, ToValue, WidgetExt};
+use crate::channel;
+use crate::capture::CapturePane;
 use crate::error_window::error;
+use crate::sequence;
+use crate::sequence::Step;
+use crate::sequence::format_delay;
+use crate::show_packet::PacketView;
+use crate::show_packet::SharedPacket;
+use crate::transmit;
+use crate::transmit::Transmission;
 use crate::widgets::MainWindowWidgets;
+use crate::widgets::parse_entry;
 
 struct StoredPacket {
     widget: gtk::Box,
-    packet: Vec<u8>
+    title: gtk::Label,
+    label: String,
+    packet: SharedPacket,
+    /* Waited before every send of the packet unless the sequence gives its own delay */
+    delay: Option<Duration>,
+    repeat: usize
 }
 impl StoredPacket {
-    pub(crate) fn new(payload: Vec<u8>, label: &str, index: usize) -> StoredPacket {
-        let full_label = gtk::Label::new(Some(&(index.to_string() + " " + label)));
+    pub(crate) fn new(payload: Vec<u8>, label: &str) -> StoredPacket {
+        let title = gtk::Label::new(None);
         let image = gtk::Image::from_file("packet_icon.png");
 
         let widget = gtk::Box::builder()
@@ -26,95 +43,173 @@ impl StoredPacket {
             .margin_bottom(10)
             .build();
         widget.append(&image);
-        widget.append(&full_label);
+        widget.append(&title);
 
-        Self {  widget,  packet: payload  }
+        /* The widget itself is dragged, the list finds both positions from it. */
+        let drag = gtk::DragSource::builder().actions(gdk::DragAction::MOVE).build();
+        let dragged = widget.clone();
+        drag.connect_prepare(move |_, _, _| Some(gdk::ContentProvider::for_value(&dragged.to_value())));
+        widget.add_controller(&drag);
+
+        Self {  widget,  title,  label: label.to_string(),  packet: Rc::new(RefCell::new(payload)),  delay: None,  repeat: 1  }
     }
 }
 
 pub(crate) struct Database {
     pub(crate) list: gtk::Box,
-    packets: Vec<StoredPacket>
+    packets: Vec<StoredPacket>,
+    selected: Option<usize>,
+    capture: CapturePane,
+    view: PacketView,
+    /* Stop flags of the sequences being sent */
+    running: Vec<Arc<AtomicBool>>
 }
 impl Database {
     pub(crate) fn new() -> Database {
         Self {
             list: gtk::Box::builder().orientation(gtk::Orientation::Horizontal).spacing(20).build(),
-            packets: Vec::new()
+            packets: Vec::new(),
+            selected: None,
+            capture: CapturePane::new(),
+            view: PacketView::new(),
+            running: Vec::new()
         }
     }
 
     pub(crate) fn push(&mut self, payload: Vec<u8>, label: &str) {
-        let item = StoredPacket::new(payload, label, self.packets.len());
-        self.list.append(&item.widget);
-        self.packets.push(item);
-    }
-
-    pub(crate) fn send_range(&self, range: RangeInclusive<usize>, iface: &str) {
-        let interfaces = datalink::interfaces();
-        let interface = interfaces.into_iter()
-            .filter(|interface: &NetworkInterface| {
-                interface.name == iface
-            })
-            .next()
-            .unwrap();
-
-        let (mut tx, _) = match datalink::channel(&interface, Default::default()) {
-            Ok(Ethernet(tx, rx)) => (tx, rx),
-            Ok(_) => panic!("Unhandled channel type."),
-            Err(_) => panic!("Failed to create datalink channel."),
-        };
+        self.packets.push(StoredPacket::new(payload, label));
+        self.select(self.packets.len() - 1);
+    }
 
-        for i in range {
-            if i > self.packets.len() {
-                error("Wrong sequence range.");
-                return
-            }
-
-            let packet = &self.packets[i];
-            match tx.send_to(&packet.packet, None) {
-                Some(_) => {}
-                None => {
-                    error(&("Failed to send packet ".to_owned() + &i.to_string()));
-                    return;
-                }
-            }
+    /* The list is rebuilt after every change, so the shown indices always match the Send Sequence dialog. */
+    fn refresh(&self) {
+        while let Some(child) = self.list.first_child() { self.list.remove(&child); }
+        for (i, item) in self.packets.iter().enumerate() {
+            let mut text = i.to_string() + " " + &item.label;
+            if let Some(delay) = item.delay { text += &format!(" @{}", format_delay(delay)); }
+            if item.repeat > 1 { text += &format!(" x{}", item.repeat); }
+            let text = glib::markup_escape_text(&text).to_string();
+            item.title.set_markup(&match self.selected == Some(i) {
+                true => format!("<b>{}</b>", text),
+                false => text
+            });
+            self.list.append(&item.widget);
         }
     }
 
-    pub(crate) fn send_multiple_times(&self, index: usize, amount: usize, iface: &str) {
-        let interfaces = datalink::interfaces();
-        let interface = interfaces.into_iter()
-            .filter(|interface: &NetworkInterface| {
-                interface.name == iface
-            })
-            .next()
-            .unwrap();
+    fn select(&mut self, index: usize) {
+        self.selected = Some(index);
+        self.refresh();
+        self.view.show(&self.packets[index].packet);
+    }
+
+    fn index_of(&self, widget: &gtk::Widget) -> Option<usize> {
+        self.packets.iter().position(|item| widget == item.widget.upcast_ref::<gtk::Widget>() || widget.is_ancestor(&item.widget))
+    }
+
+    fn delete(&mut self) {
+        let index = match self.selected {
+            Some(index) => index,
+            None => { error("Select a packet first."); return; }
+        };
+        self.packets.remove(index);
+        match self.packets.is_empty() {
+            true => self.clear(),
+            false => self.select(index.min(self.packets.len() - 1))
+        }
+    }
 
-        let (mut tx, _) = match datalink::channel(&interface, Default::default()) {
-            Ok(Ethernet(tx, rx)) => (tx, rx),
-            Ok(_) => panic!("Unhandled channel type."),
-            Err(_) => panic!("Failed to create datalink channel."),
+    fn duplicate(&mut self) {
+        let index = match self.selected {
+            Some(index) => index,
+            None => { error("Select a packet first."); return; }
         };
+        let mut copy = StoredPacket::new(self.packets[index].packet.borrow().clone(), &self.packets[index].label);
+        copy.delay = self.packets[index].delay;
+        copy.repeat = self.packets[index].repeat;
+        self.packets.insert(index + 1, copy);
+        self.select(index + 1);
+    }
 
-        if index > self.packets.len() {
-            error("Bad packet number in the queue.");
-            return;
+    fn rename(&mut self, label: &str) {
+        if let Some(index) = self.selected {
+            self.packets[index].label = label.to_string();
+            self.refresh();
         }
+    }
 
-        let packet = &self.packets[index];
-        for i in 0..amount {
-            match tx.send_to(&packet.packet, None) {
-                Some(_) => {}
-                None => {
-                    error(&("Failed to send packet ".to_owned() + &i.to_string()));
-                    return;
-                }
-            }
+    fn set_timing(&mut self, delay: Option<Duration>, repeat: usize) {
+        if let Some(index) = self.selected {
+            self.packets[index].delay = delay;
+            self.packets[index].repeat = repeat;
+            self.refresh();
         }
     }
 
+    fn move_packet(&mut self, from: usize, to: usize) {
+        let item = self.packets.remove(from);
+        let to = to.min(self.packets.len());
+        self.packets.insert(to, item);
+        self.select(to);
+    }
+
+    fn clear(&mut self) {
+        self.packets.clear();
+        self.selected = None;
+        self.refresh();
+        self.view.clear();
+    }
+
+    pub(crate) fn send_frame(&self, payload: &[u8], iface: &str, offline: bool) {
+        let (mut tx, rx) = match channel::open(iface, offline, Some(channel::POLL_INTERVAL)) {
+            Ok(channel) => channel,
+            Err(what) => { error(&what); return }
+        };
+
+        match tx.send_to(payload, None) {
+            Some(Ok(_)) => {},
+            Some(Err(what)) => { error(&("Failed to send packet: ".to_owned() + &what.to_string())); return }
+            None => { error("Failed to send packet."); return }
+        }
+
+        if self.capture.is_enabled() { self.capture.start(rx, &[payload], None); }
+    }
+
+    /* A delay written in the sequence takes precedence over the packet's own. */
+    pub(crate) fn send_sequence(&mut self, steps: &[Step], loops: Option<usize>, iface: &str, offline: bool) {
+        let frames = steps.iter().map(|step| {
+            let item = &self.packets[step.index];
+            Transmission { index: step.index, frame: item.packet.borrow().clone(), delay: step.delay.or(item.delay).unwrap_or_default(), repeat: item.repeat }
+        }).collect();
+        self.transmit(frames, loops, iface, offline);
+    }
+
+    /* Timed sending shared by sequences and replays, Stop sending stops all of them. */
+    pub(crate) fn transmit(&mut self, frames: Vec<Transmission>, loops: Option<usize>, iface: &str, offline: bool) {
+        let (tx, rx) = match channel::open(iface, offline, Some(channel::POLL_INTERVAL)) {
+            Ok(channel) => channel,
+            Err(what) => { error(&what); return }
+        };
+
+        let sent: Vec<Vec<u8>> = match self.capture.is_enabled() {
+            true => frames.iter().map(|transmission| transmission.frame.clone()).collect(),
+            false => Vec::new()
+        };
+
+        self.running.retain(|flag| Arc::strong_count(flag) > 1);
+        let (stop, sending) = transmit::start(tx, frames, loops);
+        self.running.push(stop);
+        /* The receiver was opened before sending started, so responses wait in it until the capture reads them. */
+        if self.capture.is_enabled() { self.capture.start(rx, &sent, Some(sending)); }
+    }
+
+    fn stop(&mut self) {
+        for flag in self.running.drain(..) { flag.store(true, Ordering::Relaxed); }
+    }
+
     pub(crate) fn get_ui_section(widgets: Rc<RefCell<MainWindowWidgets>>, database: Rc<RefCell<Database>>) -> gtk::Box {
+        let section_box = gtk::Box::builder().orientation(gtk::Orientation::Vertical).halign(gtk::Align::Center)
+            .valign(gtk::Align::Center).spacing(12).build();
         let main_box = gtk::Box::builder().orientation(gtk::Orientation::Horizontal).halign(gtk::Align::Center)
             .margin_start(24).margin_end(24).valign(gtk::Align::Center).spacing(24).build();
 
@@ -132,11 +227,21 @@ impl Database {
 
         let frame = gtk::Frame::builder().child(&scrolled_window).build();
         main_box.append(&frame);
+        main_box.append(&Self::prepare_queue_controls(widgets.clone(), database.clone()));
+
+        let capture_controls = database.borrow().capture.prepare_controls();
+        let capture_list = database.borrow().capture.prepare_list();
+        let view = database.borrow().view.prepare_ui();
+
+        let stop = gtk::Button::with_label("Stop sending");
+        let stopped = database.clone();
+        stop.connect_clicked(move |_| stopped.borrow_mut().stop());
 
         let sequence = gtk::Button::with_label("Send Sequence");
         main_box.append(&sequence); sequence.connect_clicked(move |_| {
             let entry = gtk::Entry::builder()
-                .placeholder_text("Packets sequence...")
+                .placeholder_text("0,3,5  2-7  (0-3)*100  1 @10ms  loop 5 { 0-4 }")
+                .width_chars(40)
                 .margin_start(5)
                 .margin_end(5)
                 .margin_top(5)
@@ -148,45 +253,32 @@ impl Database {
                 Some(&gtk::Window::new()),
                 gtk::DialogFlags::USE_HEADER_BAR,
                 &[("Ok", gtk::ResponseType::Ok), ("Cancel", gtk::ResponseType::Cancel)]);
+            let loops = gtk::Entry::builder().placeholder_text("Loops (1)").margin_start(5).margin_end(5).build();
+            let forever = gtk::CheckButton::builder().label("Loop until stopped").margin_start(5).margin_end(5).margin_bottom(5).build();
             dialog.content_area().append(&entry);
+            dialog.content_area().append(&loops);
+            dialog.content_area().append(&forever);
 
             let database_clone = database.clone();
             let widgets_clone = widgets.clone();
             dialog.connect_response(move |dialog, response| {
                 match response {
                     gtk::ResponseType::Ok => {
-                        if entry.text().contains("-") {
-                            let indexes: Vec<_> = entry.text().to_string().split("-").map(|v| v.parse::<isize>().unwrap_or(-1)).collect();
-                            if indexes.len() == 1 {
-                                match indexes[0] {
-                                    -1 => { error("Bad packets sequence"); }
-                                    _ => {
-                                        let iface = widgets_clone.borrow().get_active_interface();
-                                        database_clone.borrow().send_range(RangeInclusive::new(indexes[0] as usize, indexes[0] as usize), &iface);
-                                    }
-                                }
-                                dialog.close();
-                                return;
-                            }
-
-                            if indexes.len() != 2 || indexes[0] == -1 || indexes[1] == -1 || indexes[0] >= indexes[1] {
-                                error("Bad packet sequence. Please enter two numbers separated by '*' or '-'");
-                                dialog.close();
-                            }
-
-                            let iface = widgets_clone.borrow().get_active_interface();
-                            database_clone.borrow().send_range(RangeInclusive::new(indexes[0] as usize, indexes[1] as usize), &iface);
-                        }
-
-                        if entry.text().contains("*") {
-                            let indexes: Vec<_> = entry.text().to_string().split("*").map(|v| v.parse::<usize>().unwrap_or(0)).collect();
-                            if indexes.len() != 2 {
-                                error("Bad packet sequence. Please enter two numbers separated by '*' or '-'");
-                                dialog.close();
+                        let steps = match sequence::parse(&entry.text(), database_clone.borrow().packets.len()) {
+                            Ok(steps) => steps,
+                            Err(what) => { error(&what); return; }
+                        };
+                        let loops = match forever.is_active() {
+                            true => None,
+                            false => match parse_entry(&loops, 1usize, "loop count") {
+                                Some(0) => { error("The loop count must be at least 1."); return; }
+                                Some(loops) => Some(loops),
+                                None => return
                             }
-                            let iface = widgets_clone.borrow().get_active_interface();
-                            database_clone.borrow().send_multiple_times(indexes[0] as usize, indexes[1] as usize, &iface);
-                        }
+                        };
+                        let iface = widgets_clone.borrow().get_active_interface();
+                        let offline = widgets_clone.borrow().is_offline();
+                        database_clone.borrow_mut().send_sequence(&steps, loops, &iface, offline);
                         dialog.close();
                     },
                     gtk::ResponseType::Cancel => {
@@ -198,7 +290,149 @@ impl Database {
 
             dialog.show();
         });
+        main_box.append(&stop);
+
+        main_box.append(&capture_controls);
 
-        main_box
+        section_box.append(&main_box);
+        section_box.append(&view);
+        section_box.append(&capture_list);
+        section_box
     }
-}
\ No newline at end of file
+
+    /* Clicking selects a packet, dropping one moves it to the position of the packet under the pointer or to the end. */
+    fn prepare_queue_controls(widgets: Rc<RefCell<MainWindowWidgets>>, database: Rc<RefCell<Database>>) -> gtk::Box {
+        let list = database.borrow().list.clone();
+
+        let click = gtk::GestureClick::new();
+        let (clicked_list, clicked) = (list.clone(), database.clone());
+        click.connect_pressed(move |_, _, x, y| {
+            let index = match clicked_list.pick(x, y, gtk::PickFlags::DEFAULT) {
+                Some(widget) => clicked.borrow().index_of(&widget),
+                None => None
+            };
+            if let Some(index) = index { clicked.borrow_mut().select(index); }
+        });
+        list.add_controller(&click);
+
+        let drop = gtk::DropTarget::new(gtk::Box::static_type(), gdk::DragAction::MOVE);
+        let (drop_list, dropped) = (list.clone(), database.clone());
+        drop.connect_drop(move |_, value, x, y| {
+            let from = match value.get::<gtk::Box>() {
+                Ok(widget) => dropped.borrow().index_of(widget.upcast_ref()),
+                Err(_) => None
+            };
+            let to = drop_list.pick(x, y, gtk::PickFlags::DEFAULT).and_then(|widget| dropped.borrow().index_of(&widget));
+            let from = match from {
+                Some(from) => from,
+                None => return false
+            };
+            let to = to.unwrap_or(usize::MAX);
+            dropped.borrow_mut().move_packet(from, to);
+            true
+        });
+        list.add_controller(&drop);
+
+        let controls = gtk::Box::builder().orientation(gtk::Orientation::Vertical).valign(gtk::Align::Center).spacing(6).build();
+
+        let delete = gtk::Button::with_label("Delete");
+        let deleted = database.clone();
+        delete.connect_clicked(move |_| deleted.borrow_mut().delete());
+        controls.append(&delete);
+
+        let duplicate = gtk::Button::with_label("Duplicate");
+        let duplicated = database.clone();
+        duplicate.connect_clicked(move |_| duplicated.borrow_mut().duplicate());
+        controls.append(&duplicate);
+
+        let rename = gtk::Button::with_label("Rename...");
+        let renamed = database.clone();
+        rename.connect_clicked(move |_| {
+            let label = match renamed.borrow().selected {
+                Some(index) => renamed.borrow().packets[index].label.clone(),
+                None => { error("Select a packet first."); return; }
+            };
+            let entry = gtk::Entry::builder().text(&label).margin_start(5).margin_end(5).margin_top(5).margin_bottom(5).build();
+
+            let dialog = gtk::Dialog::with_buttons(
+                Some("Rename packet"),
+                Some(&gtk::Window::new()),
+                gtk::DialogFlags::USE_HEADER_BAR,
+                &[("Ok", gtk::ResponseType::Ok), ("Cancel", gtk::ResponseType::Cancel)]);
+            dialog.content_area().append(&entry);
+
+            let renamed = renamed.clone();
+            dialog.connect_response(move |dialog, response| {
+                if response == gtk::ResponseType::Ok { renamed.borrow_mut().rename(&entry.text()); }
+                dialog.close();
+            });
+            dialog.show();
+        });
+        controls.append(&rename);
+
+        let timing = gtk::Button::with_label("Timing...");
+        let timed = database.clone();
+        timing.connect_clicked(move |_| {
+            let (delay, repeat) = match timed.borrow().selected {
+                Some(index) => (timed.borrow().packets[index].delay, timed.borrow().packets[index].repeat),
+                None => { error("Select a packet first."); return; }
+            };
+            let delay_entry = gtk::Entry::builder().text(&delay.map(format_delay).unwrap_or_default()).placeholder_text("10ms, 500us, 2s").build();
+            let repeat_entry = gtk::Entry::builder().text(&repeat.to_string()).build();
+
+            let grid = gtk::Grid::builder().margin_start(24).margin_end(24).margin_top(24).margin_bottom(24)
+                .row_spacing(24).column_spacing(24).build();
+            grid.attach(&gtk::Label::builder().label("Delay before send (up to 1h)").halign(gtk::Align::Start).build(), 0, 0, 1, 1);
+            grid.attach(&delay_entry, 1, 0, 1, 1);
+            grid.attach(&gtk::Label::builder().label("Repeat").halign(gtk::Align::Start).build(), 0, 1, 1, 1);
+            grid.attach(&repeat_entry, 1, 1, 1, 1);
+
+            let dialog = gtk::Dialog::with_buttons(
+                Some("Packet timing"),
+                Some(&gtk::Window::new()),
+                gtk::DialogFlags::USE_HEADER_BAR,
+                &[("Ok", gtk::ResponseType::Ok), ("Cancel", gtk::ResponseType::Cancel)]);
+            dialog.content_area().append(&grid);
+
+            let timed = timed.clone();
+            dialog.connect_response(move |dialog, response| {
+                if response == gtk::ResponseType::Ok {
+                    let delay = match delay_entry.text_length() {
+                        0 => None,
+                        _ => match sequence::parse_delay(&delay_entry.text()) {
+                            Ok(delay) => Some(delay),
+                            Err(what) => { error(&what); return; }
+                        }
+                    };
+                    let repeat = match parse_entry(&repeat_entry, 1usize, "repeat count") {
+                        Some(0) => { error("The repeat count must be at least 1."); return; }
+                        Some(repeat) => repeat,
+                        None => return
+                    };
+                    timed.borrow_mut().set_timing(delay, repeat);
+                }
+                dialog.close();
+            });
+            dialog.show();
+        });
+        controls.append(&timing);
+
+        let load = gtk::Button::with_label("Load into editor");
+        let loaded = database.clone();
+        load.connect_clicked(move |_| {
+            let frame = match loaded.borrow().selected {
+                Some(index) => loaded.borrow().packets[index].packet.borrow().clone(),
+                None => { error("Select a packet first."); return; }
+            };
+            widgets.borrow_mut().load_frame(&frame);
+        });
+        controls.append(&load);
+
+        let clear = gtk::Button::with_label("Clear all");
+        clear.connect_clicked(move |_| database.borrow_mut().clear());
+        controls.append(&clear);
+
+        controls
+    }
+}
+
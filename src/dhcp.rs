@@ -0,0 +1,195 @@
+use gtk::prelude::*;
+use rand::Rng;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use pnet::util::MacAddr;
+
+use crate::error_window::error;
+use crate::widgets::parse_entry;
+use crate::widgets::parse_hex;
+
+pub(crate) const CLIENT_PORT: u16 = 68;
+pub(crate) const SERVER_PORT: u16 = 67;
+
+/* Message type option values in dropdown order. */
+const MESSAGE_TYPES: [(&str, u8); 8] = [("DISCOVER", 1), ("OFFER", 2), ("REQUEST", 3), ("DECLINE", 4),
+    ("ACK", 5), ("NAK", 6), ("RELEASE", 7), ("INFORM", 8)];
+
+fn parse_address(entry: &gtk::Entry, what: &str) -> Option<Ipv4Addr> {
+    parse_entry(entry, Ipv4Addr::UNSPECIFIED, what)
+}
+
+fn push_option(options: &mut Vec<u8>, code: u8, data: &[u8]) -> Option<()> {
+    if data.len() > 255 { error(&format!("DHCP option {} is longer than 255 bytes", code)); return None; }
+    options.push(code);
+    options.push(data.len() as u8);
+    options.extend_from_slice(data);
+    Some(())
+}
+
+pub(crate) struct DhcpWidgets {
+    message_type: gtk::DropDown,
+    xid: gtk::Entry,
+    broadcast: gtk::CheckButton,
+    ciaddr: gtk::Entry,
+    yiaddr: gtk::Entry,
+    siaddr: gtk::Entry,
+    giaddr: gtk::Entry,
+    chaddr: gtk::Entry,
+
+    requested_ip: gtk::Entry,
+    server_id: gtk::Entry,
+    lease_time: gtk::Entry,
+    parameters: gtk::Entry,
+    client_id: (gtk::CheckButton, gtk::Entry),
+    circuit_id: gtk::Entry,
+    remote_id: gtk::Entry,
+    extra: gtk::Entry
+}
+impl DhcpWidgets {
+    pub(crate) fn new() -> Self {
+        let names: Vec<&str> = MESSAGE_TYPES.iter().map(|(name, _)| *name).collect();
+        Self {
+            message_type: gtk::DropDown::from_strings(&names),
+            xid: gtk::Entry::builder().placeholder_text("Transaction ID (random)").build(),
+            broadcast: gtk::CheckButton::builder().label("Broadcast flag").active(true).build(),
+            ciaddr: gtk::Entry::builder().placeholder_text("0.0.0.0").build(),
+            yiaddr: gtk::Entry::builder().placeholder_text("0.0.0.0").build(),
+            siaddr: gtk::Entry::builder().placeholder_text("0.0.0.0").build(),
+            giaddr: gtk::Entry::builder().placeholder_text("0.0.0.0").build(),
+            chaddr: gtk::Entry::builder().placeholder_text("Source MAC").build(),
+
+            requested_ip: gtk::Entry::builder().placeholder_text("Requested IP (50)").build(),
+            server_id: gtk::Entry::builder().placeholder_text("Server identifier (54)").build(),
+            lease_time: gtk::Entry::builder().placeholder_text("Lease time, s (51)").build(),
+            parameters: gtk::Entry::builder().placeholder_text("Parameter request list (55): 1,3,6,15").build(),
+            client_id: (gtk::CheckButton::builder().label("Auto").active(true).build(),
+                        gtk::Entry::builder().placeholder_text("Client ID (61), hex").build()),
+            circuit_id: gtk::Entry::builder().placeholder_text("Option 82 circuit ID").build(),
+            remote_id: gtk::Entry::builder().placeholder_text("Option 82 remote ID").build(),
+            extra: gtk::Entry::builder().placeholder_text("Other options: code:hex, ...").build()
+        }
+    }
+
+    /* The defaults button sets client addressing in the main window and the ports. */
+    pub(crate) fn prepare_ui_fields(&self, apply_defaults: impl Fn() + 'static) -> gtk::Box {
+        let grid = gtk::Grid::builder().margin_start(24).margin_end(24).margin_top(24).row_spacing(12)
+            .halign(gtk::Align::Center).valign(gtk::Align::Center).column_spacing(24).build();
+
+        grid.attach(&gtk::Label::builder().label("Message type").halign(gtk::Align::Start).build(), 0, 0, 1, 1);
+        grid.attach(&self.message_type, 1, 0, 1, 1);
+        grid.attach(&self.xid, 2, 0, 1, 1);
+        grid.attach(&self.broadcast, 3, 0, 1, 1);
+
+        grid.attach(&gtk::Label::builder().label("ciaddr").halign(gtk::Align::Start).build(), 0, 1, 1, 1);
+        grid.attach(&self.ciaddr, 1, 1, 1, 1);
+        grid.attach(&gtk::Label::builder().label("yiaddr").halign(gtk::Align::Start).build(), 2, 1, 1, 1);
+        grid.attach(&self.yiaddr, 3, 1, 1, 1);
+        grid.attach(&gtk::Label::builder().label("siaddr").halign(gtk::Align::Start).build(), 0, 2, 1, 1);
+        grid.attach(&self.siaddr, 1, 2, 1, 1);
+        grid.attach(&gtk::Label::builder().label("giaddr").halign(gtk::Align::Start).build(), 2, 2, 1, 1);
+        grid.attach(&self.giaddr, 3, 2, 1, 1);
+        grid.attach(&gtk::Label::builder().label("chaddr").halign(gtk::Align::Start).build(), 0, 3, 1, 1);
+        grid.attach(&self.chaddr, 1, 3, 1, 1);
+
+        let defaults = gtk::Button::with_label("Use client defaults");
+        defaults.connect_clicked(move |_| apply_defaults());
+        grid.attach(&defaults, 3, 3, 1, 1);
+
+        let options_grid = gtk::Grid::builder().margin_start(6).margin_end(6).margin_top(6).margin_bottom(6)
+            .row_spacing(6).column_spacing(12).build();
+        options_grid.attach(&self.requested_ip, 0, 0, 1, 1);
+        options_grid.attach(&self.server_id, 1, 0, 1, 1);
+        options_grid.attach(&self.lease_time, 2, 0, 1, 1);
+        options_grid.attach(&self.parameters, 0, 1, 2, 1);
+        options_grid.attach(&self.client_id.0, 2, 1, 1, 1);
+        options_grid.attach(&self.client_id.1, 3, 1, 1, 1);
+        options_grid.attach(&self.circuit_id, 0, 2, 1, 1);
+        options_grid.attach(&self.remote_id, 1, 2, 1, 1);
+        options_grid.attach(&self.extra, 2, 2, 2, 1);
+
+        let main_box = gtk::Box::builder().orientation(gtk::Orientation::Vertical).spacing(12).margin_bottom(12).build();
+        main_box.append(&grid);
+        main_box.append(&gtk::Frame::builder().label("Options").child(&options_grid).margin_start(24).margin_end(24).build());
+        main_box
+    }
+
+    pub(crate) fn build_message(&self, source_mac: MacAddr) -> Option<Vec<u8>> {
+        let message_type = MESSAGE_TYPES[(self.message_type.selected() as usize).min(MESSAGE_TYPES.len() - 1)].1;
+        let op = match message_type {
+            2 | 5 | 6 => 2,
+            _ => 1
+        };
+        let xid = parse_entry(&self.xid, rand::thread_rng().gen::<u32>(), "DHCP transaction ID")?;
+        let chaddr = match self.chaddr.text().is_empty() {
+            true => source_mac,
+            false => match MacAddr::from_str(&self.chaddr.text().replace('.', ":")) {
+                Ok(address) => address,
+                Err(_) => { error("Bad DHCP chaddr value"); return None; }
+            }
+        };
+
+        let mut message = vec![op, 1, 6, 0];
+        message.extend_from_slice(&xid.to_be_bytes());
+        message.extend_from_slice(&[0, 0]);
+        message.extend_from_slice(&(if self.broadcast.is_active() { 0x8000u16 } else { 0 }).to_be_bytes());
+        for (entry, what) in [(&self.ciaddr, "ciaddr"), (&self.yiaddr, "yiaddr"), (&self.siaddr, "siaddr"), (&self.giaddr, "giaddr")] {
+            message.extend_from_slice(&parse_address(entry, what)?.octets());
+        }
+        message.extend_from_slice(&chaddr.octets());
+        message.extend_from_slice(&[0; 10 + 64 + 128]);
+        message.extend_from_slice(&[99, 130, 83, 99]);
+
+        message.extend(self.options(message_type, chaddr)?);
+        message.push(255);
+        Some(message)
+    }
+
+    fn options(&self, message_type: u8, chaddr: MacAddr) -> Option<Vec<u8>> {
+        let mut options = Vec::new();
+        push_option(&mut options, 53, &[message_type])?;
+
+        if !self.requested_ip.text().is_empty() {
+            push_option(&mut options, 50, &parse_address(&self.requested_ip, "requested IP")?.octets())?;
+        }
+        if !self.server_id.text().is_empty() {
+            push_option(&mut options, 54, &parse_address(&self.server_id, "server identifier")?.octets())?;
+        }
+        if !self.lease_time.text().is_empty() {
+            push_option(&mut options, 51, &parse_entry(&self.lease_time, 0u32, "lease time")?.to_be_bytes())?;
+        }
+        if !self.parameters.text().is_empty() {
+            let text = self.parameters.text();
+            match text.split(',').map(|v| v.trim().parse::<u8>()).collect::<Result<Vec<_>, _>>() {
+                Ok(list) => push_option(&mut options, 55, &list)?,
+                Err(_) => { error("Bad DHCP parameter request list"); return None; }
+            }
+        }
+
+        let client_id = match self.client_id.0.is_active() {
+            true => Some([&[1u8][..], &chaddr.octets()].concat()),
+            false if self.client_id.1.text().is_empty() => None,
+            false => match parse_hex(&self.client_id.1.text()) {
+                Some(bytes) => Some(bytes),
+                None => { error("Bad DHCP client ID value"); return None; }
+            }
+        };
+        if let Some(client_id) = client_id { push_option(&mut options, 61, &client_id)?; }
+
+        for extra in self.extra.text().split(',').map(str::trim).filter(|v| !v.is_empty()) {
+            let parsed = extra.split_once(':').and_then(|(code, data)| Some((code.trim().parse::<u8>().ok()?, parse_hex(data)?)));
+            match parsed {
+                Some((code, data)) => push_option(&mut options, code, &data)?,
+                None => { error(&("Bad DHCP option ".to_owned() + extra)); return None; }
+            }
+        }
+
+        /* Relay agent information goes last, as relays append it. */
+        let mut relay = Vec::new();
+        if !self.circuit_id.text().is_empty() { push_option(&mut relay, 1, self.circuit_id.text().as_bytes())?; }
+        if !self.remote_id.text().is_empty() { push_option(&mut relay, 2, self.remote_id.text().as_bytes())?; }
+        if !relay.is_empty() { push_option(&mut options, 82, &relay)?; }
+
+        Some(options)
+    }
+}
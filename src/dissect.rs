@@ -0,0 +1,718 @@
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use pnet::packet::ethernet::EtherType;
+use pnet::packet::ethernet::EtherTypes;
+use pnet::packet::ethernet::EthernetPacket;
+use pnet::packet::ip::IpNextHeaderProtocol;
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::tcp::TcpFlags;
+
+use crate::ethernet::CUSTOMER_TPID;
+use crate::ethernet::SERVICE_TPID;
+
+pub(crate) fn summary(frame: &[u8]) -> String {
+    let ethernet = match EthernetPacket::new(frame) {
+        Some(ethernet) => ethernet,
+        None => return format!("Truncated frame, {} bytes", frame.len())
+    };
+
+    let join = |values: Vec<String>| values.join("/");
+    let mut prefix = String::new();
+    let vlans = vlan_ids(frame);
+    if !vlans.is_empty() { prefix += &format!("VLAN {} ", join(vlans.iter().map(|vid| vid.to_string()).collect())); }
+    let labels = mpls_labels(frame);
+    if !labels.is_empty() { prefix += &format!("MPLS {} ", join(labels.iter().map(|label| label.to_string()).collect())); }
+    let (ethertype, payload) = link_payload(frame).unwrap_or((ethernet.get_ethertype().0, &[]));
+
+    prefix + &match EtherType::new(ethertype) {
+        EtherTypes::Ipv4 => ipv4_summary(payload),
+        EtherTypes::Ipv6 => ipv6_summary(payload),
+        EtherTypes::Arp => format!("{} → {} ARP", ethernet.get_source(), ethernet.get_destination()),
+        EtherType(length) if length <= 1500 =>
+            format!("{} → {} 802.3 LLC, length {}", ethernet.get_source(), ethernet.get_destination(), length),
+        other => format!("{} → {} EtherType 0x{:04x}, {} bytes", ethernet.get_source(), ethernet.get_destination(), other.0, frame.len())
+    }
+}
+
+fn is_vlan_tpid(tpid: u16) -> bool {
+    matches!(tpid, CUSTOMER_TPID | SERVICE_TPID | 0x9100)
+}
+
+fn is_mpls(ethertype: u16) -> bool {
+    matches!(ethertype, 0x8847 | 0x8848)
+}
+
+/* EtherType and payload of a frame with any VLAN tags and MPLS labels skipped. The protocol under
+ * an MPLS stack is guessed from the IP version nibble. */
+pub(crate) fn link_payload(frame: &[u8]) -> Option<(u16, &[u8])> {
+    let mut offset = 12 + 4 * vlan_ids(frame).len();
+    let ethertype = frame.get(offset..offset + 2).map(|v| u16::from_be_bytes([v[0], v[1]]))?;
+    offset += 2;
+    if !is_mpls(ethertype) { return Some((ethertype, frame.get(offset..)?)); }
+
+    let payload = frame.get(offset + 4 * mpls_labels(frame).len()..)?;
+    match payload.first()? >> 4 {
+        4 => Some((0x0800, payload)),
+        6 => Some((0x86dd, payload)),
+        _ => Some((ethertype, payload))
+    }
+}
+
+fn vlan_ids(frame: &[u8]) -> Vec<u16> {
+    let mut result = Vec::new();
+    let mut offset = 12;
+    while let Some(tag) = frame.get(offset..offset + 4) {
+        if !is_vlan_tpid(u16::from_be_bytes([tag[0], tag[1]])) { break; }
+        result.push(u16::from_be_bytes([tag[2] & 0x0f, tag[3]]));
+        offset += 4;
+    }
+    result
+}
+
+fn mpls_labels(frame: &[u8]) -> Vec<u32> {
+    let mut result = Vec::new();
+    let mut offset = 12 + 4 * vlan_ids(frame).len();
+    if !frame.get(offset..offset + 2).is_some_and(|v| is_mpls(u16::from_be_bytes([v[0], v[1]]))) { return result; }
+
+    offset += 2;
+    while let Some(entry) = frame.get(offset..offset + 4) {
+        let entry = u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]);
+        result.push(entry >> 12);
+        offset += 4;
+        if entry & 0x100 != 0 { break; }
+    }
+    result
+}
+
+pub(crate) fn ipv4_payload(frame: &[u8]) -> Option<&[u8]> {
+    match link_payload(frame)? {
+        (0x0800, payload) => Some(payload),
+        _ => None
+    }
+}
+
+/* Works on the truncated datagrams quoted by ICMP errors as well, so transport fields are read by hand. */
+fn ipv4_summary(data: &[u8]) -> String {
+    let ip = match Ipv4Packet::new(data) {
+        Some(ip) => ip,
+        None => return format!("Truncated IPv4 packet, {} bytes", data.len())
+    };
+    let transport = data.get(ip.get_header_length() as usize * 4..).unwrap_or(&[]);
+    let port = |offset: usize| transport.get(offset..offset + 2).map(|v| u16::from_be_bytes([v[0], v[1]])).unwrap_or(0);
+    let word = |offset: usize| transport.get(offset..offset + 4).map(|v| u32::from_be_bytes([v[0], v[1], v[2], v[3]])).unwrap_or(0);
+
+    match ip.get_next_level_protocol() {
+        IpNextHeaderProtocols::Tcp => {
+            let flags = transport.get(12..14).map(|v| u16::from_be_bytes([v[0] & 0x01, v[1]])).unwrap_or(0);
+            let header_length = transport.get(12).map(|v| (v >> 4) as usize * 4).unwrap_or(20);
+            format!("{}:{} → {}:{} TCP [{}] seq={} ack={} win={} len={}", ip.get_source(), port(0), ip.get_destination(), port(2),
+                    tcp_flags(flags), word(4), word(8), port(14), transport.len().saturating_sub(header_length))
+        },
+        IpNextHeaderProtocols::Udp =>
+            format!("{}:{} → {}:{} UDP len={}", ip.get_source(), port(0), ip.get_destination(), port(2), port(4).saturating_sub(8)),
+        IpNextHeaderProtocols::Icmp => {
+            let icmp_type = transport.first().cloned().unwrap_or(0);
+            let icmp_code = transport.get(1).cloned().unwrap_or(0);
+            let mut result = format!("{} → {} ICMP {} code {}", ip.get_source(), ip.get_destination(), icmp_type_name(icmp_type), icmp_code);
+            match icmp_type {
+                0 | 8 => result += &format!(" id={} seq={}", port(4), port(6)),
+                3 | 4 | 5 | 11 | 12 if transport.len() > 8 => result += &(" quoting ".to_owned() + &ipv4_summary(&transport[8..])),
+                _ => {}
+            }
+            result
+        },
+        IpNextHeaderProtocols::Igmp => {
+            let kind = match transport.first() {
+                Some(0x11) => "Membership query",
+                Some(0x12) => "v1 Membership report",
+                Some(0x16) => "v2 Membership report",
+                Some(0x17) => "Leave group",
+                Some(0x22) => "v3 Membership report",
+                _ => "unknown"
+            };
+            let group = transport.get(4..8).map(|v| Ipv4Addr::new(v[0], v[1], v[2], v[3]).to_string()).unwrap_or_default();
+            format!("{} → {} IGMP {} {}", ip.get_source(), ip.get_destination(), kind, group)
+        },
+        IpNextHeaderProtocols::Sctp => {
+            let mut chunks = Vec::new();
+            let mut offset = 12;
+            while let Some(header) = transport.get(offset..offset + 4) {
+                chunks.push(sctp_chunk_name(header[0]));
+                offset += ((u16::from_be_bytes([header[2], header[3]]) as usize + 3) & !3).max(4);
+            }
+            format!("{}:{} → {}:{} SCTP tag=0x{:08x} [{}]", ip.get_source(), port(0), ip.get_destination(), port(2), word(4), chunks.join(", "))
+        },
+        other => format!("{} → {} IP protocol {}, {} bytes", ip.get_source(), ip.get_destination(), other.0, data.len())
+    }
+}
+
+/* Extension headers are not walked, anything but ICMPv6 is shown by its next header value. */
+fn ipv6_summary(data: &[u8]) -> String {
+    if data.len() < 40 { return format!("Truncated IPv6 packet, {} bytes", data.len()); }
+    let address = |offset: usize| Ipv6Addr::from(<[u8; 16]>::try_from(&data[offset..offset + 16]).unwrap_or([0; 16]));
+    let (source, destination) = (address(8), address(24));
+    let message = &data[40..];
+    if data[6] != 58 { return format!("{} → {} IPv6 next header {}, {} bytes", source, destination, data[6], data.len()); }
+
+    let kind = match message.first().cloned().unwrap_or(0) {
+        1 => "Destination unreachable".to_string(),
+        2 => "Packet too big".to_string(),
+        3 => "Time exceeded".to_string(),
+        4 => "Parameter problem".to_string(),
+        128 => "Echo request".to_string(),
+        129 => "Echo reply".to_string(),
+        133 => "Router solicitation".to_string(),
+        134 => "Router advertisement".to_string(),
+        135 => "Neighbor solicitation".to_string(),
+        136 => "Neighbor advertisement".to_string(),
+        137 => "Redirect".to_string(),
+        other => format!("type {}", other)
+    };
+    let mut result = format!("{} → {} ICMPv6 {} code {}", source, destination, kind, message.get(1).cloned().unwrap_or(0));
+    match message.first() {
+        Some(128 | 129) if message.len() >= 8 =>
+            result += &format!(" id={} seq={}", u16::from_be_bytes([message[4], message[5]]), u16::from_be_bytes([message[6], message[7]])),
+        Some(135..=137) if message.len() >= 24 =>
+            result += &format!(" target={}", Ipv6Addr::from(<[u8; 16]>::try_from(&message[8..24]).unwrap_or([0; 16]))),
+        _ => {}
+    }
+    result
+}
+
+fn sctp_chunk_name(kind: u8) -> String {
+    match kind {
+        0 => "DATA".to_string(),
+        1 => "INIT".to_string(),
+        2 => "INIT-ACK".to_string(),
+        3 => "SACK".to_string(),
+        4 => "HEARTBEAT".to_string(),
+        5 => "HEARTBEAT-ACK".to_string(),
+        6 => "ABORT".to_string(),
+        7 => "SHUTDOWN".to_string(),
+        8 => "SHUTDOWN-ACK".to_string(),
+        9 => "ERROR".to_string(),
+        10 => "COOKIE-ECHO".to_string(),
+        11 => "COOKIE-ACK".to_string(),
+        14 => "SHUTDOWN-COMPLETE".to_string(),
+        other => format!("type {}", other)
+    }
+}
+
+pub(crate) fn tcp_flags(flags: u16) -> String {
+    let names = [(TcpFlags::NS, "NS"), (TcpFlags::CWR, "CWR"), (TcpFlags::ECE, "ECE"), (TcpFlags::URG, "URG"),
+        (TcpFlags::ACK, "ACK"), (TcpFlags::PSH, "PSH"), (TcpFlags::RST, "RST"), (TcpFlags::SYN, "SYN"), (TcpFlags::FIN, "FIN")];
+    names.iter().filter(|(flag, _)| flags & flag != 0).map(|(_, name)| *name).collect::<Vec<_>>().join(", ")
+}
+
+pub(crate) fn icmp_type_name(icmp_type: u8) -> String {
+    match icmp_type {
+        0 => "Echo reply".to_string(),
+        3 => "Destination unreachable".to_string(),
+        4 => "Source quench".to_string(),
+        5 => "Redirect".to_string(),
+        8 => "Echo request".to_string(),
+        11 => "Time exceeded".to_string(),
+        12 => "Parameter problem".to_string(),
+        13 => "Timestamp".to_string(),
+        14 => "Timestamp reply".to_string(),
+        other => format!("type {}", other)
+    }
+}
+
+/* A decoded field with its absolute position in the frame, for the dissection view. */
+pub(crate) struct Field {
+    pub(crate) name: String,
+    pub(crate) value: String,
+    pub(crate) offset: usize,
+    pub(crate) length: usize,
+    pub(crate) children: Vec<Field>
+}
+impl Field {
+    fn new(name: &str, value: String, offset: usize, length: usize) -> Field {
+        Field { name: name.to_string(), value, offset, length, children: Vec::new() }
+    }
+    fn with(mut self, children: Vec<Field>) -> Field {
+        self.children = children;
+        self
+    }
+}
+
+fn be(data: &[u8], offset: usize, length: usize) -> u32 {
+    data.get(offset..offset + length).map(|v| v.iter().fold(0, |acc, byte| acc << 8 | *byte as u32)).unwrap_or(0)
+}
+
+fn checksum_field(offset: usize, stored: u16, computed: u16) -> Field {
+    let verdict = match stored == computed {
+        true => "correct".to_string(),
+        false => format!("incorrect, should be 0x{:04x}", computed)
+    };
+    Field::new("Checksum", format!("0x{:04x} ({})", stored, verdict), offset, 2)
+}
+
+fn data_field(name: &str, data: &[u8], offset: usize) -> Field {
+    let mut preview = crate::widgets::escape(&data[..data.len().min(48)]);
+    if data.len() > 48 { preview += "…"; }
+    Field::new(name, format!("{} bytes: {}", data.len(), preview), offset, data.len())
+}
+
+fn protocol_name(protocol: u8) -> String {
+    match protocol {
+        1 => "ICMP (1)".to_string(),
+        2 => "IGMP (2)".to_string(),
+        4 => "IP-in-IP (4)".to_string(),
+        6 => "TCP (6)".to_string(),
+        17 => "UDP (17)".to_string(),
+        47 => "GRE (47)".to_string(),
+        58 => "ICMPv6 (58)".to_string(),
+        132 => "SCTP (132)".to_string(),
+        other => other.to_string()
+    }
+}
+
+/* Layers of a frame as a tree; truncated layers are decoded as far as the bytes go. */
+pub(crate) fn fields(frame: &[u8]) -> Vec<Field> {
+    let mut layers = Vec::new();
+    if frame.len() < 14 {
+        layers.push(data_field("Truncated frame", frame, 0));
+        return layers;
+    }
+
+    let mac = |offset: usize| frame[offset..offset + 6].iter().map(|v| format!("{:02x}", v)).collect::<Vec<_>>().join(":");
+    let mut children = vec![Field::new("Destination", mac(0), 0, 6), Field::new("Source", mac(6), 6, 6)];
+    let mut offset = 12;
+    for vid in vlan_ids(frame) {
+        let tpid = be(frame, offset, 2);
+        let name = if tpid == SERVICE_TPID as u32 { "802.1ad service tag" } else { "802.1Q tag" };
+        children.push(Field::new(name, format!("VID {}", vid), offset, 4).with(vec![
+            Field::new("TPID", format!("0x{:04x}", tpid), offset, 2),
+            Field::new("Priority", (frame[offset + 2] >> 5).to_string(), offset + 2, 1),
+            Field::new("Drop eligible", (frame[offset + 2] >> 4 & 1).to_string(), offset + 2, 1),
+            Field::new("VLAN ID", vid.to_string(), offset + 2, 2)
+        ]));
+        offset += 4;
+    }
+    let ethertype = be(frame, offset, 2) as u16;
+    match ethertype {
+        0..=1500 => children.push(Field::new("Length", ethertype.to_string(), offset, 2)),
+        _ => children.push(Field::new("EtherType", format!("0x{:04x}", ethertype), offset, 2))
+    }
+    offset += 2;
+    for label in mpls_labels(frame) {
+        let entry = be(frame, offset, 4);
+        children.push(Field::new("MPLS label", label.to_string(), offset, 4).with(vec![
+            Field::new("Label", label.to_string(), offset, 3),
+            Field::new("Traffic class", (entry >> 9 & 7).to_string(), offset + 2, 1),
+            Field::new("Bottom of stack", (entry >> 8 & 1).to_string(), offset + 2, 1),
+            Field::new("Time to live", (entry & 0xff).to_string(), offset + 3, 1)
+        ]));
+        offset += 4;
+    }
+    let name = if ethertype <= 1500 { "IEEE 802.3" } else { "Ethernet II" };
+    layers.push(Field::new(name, format!("{} → {}", mac(6), mac(0)), 0, offset).with(children));
+
+    let (ethertype, payload) = link_payload(frame).unwrap_or((ethertype, &[]));
+    match ethertype {
+        0x0800 => ipv4_fields(payload, offset, &mut layers),
+        0x86dd => ipv6_fields(payload, offset, &mut layers),
+        0x0806 if payload.len() >= 28 => {
+            let address = |at: usize| Ipv4Addr::new(payload[at], payload[at + 1], payload[at + 2], payload[at + 3]).to_string();
+            let hardware = |at: usize| payload[at..at + 6].iter().map(|v| format!("{:02x}", v)).collect::<Vec<_>>().join(":");
+            let operation = match be(payload, 6, 2) { 1 => "request".to_string(), 2 => "reply".to_string(), other => other.to_string() };
+            layers.push(Field::new("ARP", operation.clone(), offset, 28).with(vec![
+                Field::new("Hardware type", be(payload, 0, 2).to_string(), offset, 2),
+                Field::new("Protocol type", format!("0x{:04x}", be(payload, 2, 2)), offset + 2, 2),
+                Field::new("Hardware size", payload[4].to_string(), offset + 4, 1),
+                Field::new("Protocol size", payload[5].to_string(), offset + 5, 1),
+                Field::new("Operation", operation, offset + 6, 2),
+                Field::new("Sender MAC", hardware(8), offset + 8, 6),
+                Field::new("Sender IP", address(14), offset + 14, 4),
+                Field::new("Target MAC", hardware(18), offset + 18, 6),
+                Field::new("Target IP", address(24), offset + 24, 4)
+            ]));
+        },
+        0..=1500 if payload.len() >= 3 => {
+            let mut llc = vec![
+                Field::new("DSAP", format!("0x{:02x}", payload[0]), offset, 1),
+                Field::new("SSAP", format!("0x{:02x}", payload[1]), offset + 1, 1),
+                Field::new("Control", format!("0x{:02x}", payload[2]), offset + 2, 1)
+            ];
+            let mut header = 3;
+            if payload[..2] == [0xaa, 0xaa] && payload.len() >= 8 {
+                llc.push(Field::new("SNAP OUI", format!("{:06x}", be(payload, 3, 3)), offset + 3, 3));
+                llc.push(Field::new("SNAP protocol", format!("0x{:04x}", be(payload, 6, 2)), offset + 6, 2));
+                header = 8;
+            }
+            layers.push(Field::new("Logical-Link Control", String::new(), offset, header).with(llc));
+            layers.push(data_field("Data", &payload[header..], offset + header));
+        },
+        _ => layers.push(data_field("Data", payload, offset))
+    }
+    layers
+}
+
+fn ipv4_fields(data: &[u8], base: usize, layers: &mut Vec<Field>) {
+    let header_length = data.first().map(|v| (v & 0x0f) as usize * 4).unwrap_or(0);
+    let header = match data.get(..header_length) {
+        Some(header) if header_length >= 20 => header,
+        _ => { layers.push(data_field("Truncated IPv4 packet", data, base)); return; }
+    };
+    let source = Ipv4Addr::new(data[12], data[13], data[14], data[15]);
+    let destination = Ipv4Addr::new(data[16], data[17], data[18], data[19]);
+    let fragment = be(data, 6, 2);
+    let flags = [(0x8000, "Reserved"), (0x4000, "DF"), (0x2000, "MF")].iter()
+        .filter(|(flag, _)| fragment & flag != 0).map(|(_, name)| *name).collect::<Vec<_>>().join(", ");
+    let flags = if flags.is_empty() { "none".to_string() } else { flags };
+
+    let mut children = vec![
+        Field::new("Version", (data[0] >> 4).to_string(), base, 1),
+        Field::new("Header length", format!("{} bytes", header_length), base, 1),
+        Field::new("DSCP", (data[1] >> 2).to_string(), base + 1, 1),
+        Field::new("ECN", (data[1] & 3).to_string(), base + 1, 1),
+        Field::new("Total length", be(data, 2, 2).to_string(), base + 2, 2),
+        Field::new("Identification", format!("0x{:04x}", be(data, 4, 2)), base + 4, 2),
+        Field::new("Flags", flags, base + 6, 1),
+        Field::new("Fragment offset", format!("{} bytes", (fragment & 0x1fff) * 8), base + 6, 2),
+        Field::new("Time to live", data[8].to_string(), base + 8, 1),
+        Field::new("Protocol", protocol_name(data[9]), base + 9, 1),
+        checksum_field(base + 10, be(data, 10, 2) as u16, pnet::util::checksum(header, 5)),
+        Field::new("Source", source.to_string(), base + 12, 4),
+        Field::new("Destination", destination.to_string(), base + 16, 4)
+    ];
+    if header_length > 20 { children.push(data_field("Options", &header[20..], base + 20)); }
+    layers.push(Field::new("Internet Protocol version 4", format!("{} → {}", source, destination), base, header_length).with(children));
+
+    /* Ethernet padding past the total length is not part of the packet. */
+    let end = (be(data, 2, 2) as usize).clamp(header_length, data.len());
+    let payload = &data[header_length..end];
+    let base = base + header_length;
+    if fragment & 0x1fff != 0 {
+        layers.push(data_field("Fragment data", payload, base));
+        return;
+    }
+
+    let pseudo_checksum = |skipword: usize| {
+        pnet::util::ipv4_checksum(payload, skipword, &[], &source, &destination, IpNextHeaderProtocol::new(data[9]))
+    };
+    match data[9] {
+        6 if payload.len() >= 20 => {
+            let data_offset = ((payload[12] >> 4) as usize * 4).clamp(20, payload.len());
+            let flags = be(payload, 12, 2) as u16 & 0x01ff;
+            let mut children = vec![
+                Field::new("Source port", be(payload, 0, 2).to_string(), base, 2),
+                Field::new("Destination port", be(payload, 2, 2).to_string(), base + 2, 2),
+                Field::new("Sequence number", be(payload, 4, 4).to_string(), base + 4, 4),
+                Field::new("Acknowledgment number", be(payload, 8, 4).to_string(), base + 8, 4),
+                Field::new("Header length", format!("{} bytes", data_offset), base + 12, 1),
+                Field::new("Flags", format!("0x{:03x} [{}]", flags, tcp_flags(flags)), base + 12, 2),
+                Field::new("Window", be(payload, 14, 2).to_string(), base + 14, 2),
+                checksum_field(base + 16, be(payload, 16, 2) as u16, pseudo_checksum(8)),
+                Field::new("Urgent pointer", be(payload, 18, 2).to_string(), base + 18, 2)
+            ];
+            if data_offset > 20 { children.push(data_field("Options", &payload[20..data_offset], base + 20)); }
+            let summary = format!("{} → {} [{}]", be(payload, 0, 2), be(payload, 2, 2), tcp_flags(flags));
+            layers.push(Field::new("Transmission Control Protocol", summary, base, data_offset).with(children));
+            if data_offset < payload.len() { layers.push(data_field("Data", &payload[data_offset..], base + data_offset)); }
+        },
+        17 if payload.len() >= 8 => {
+            let stored = be(payload, 6, 2) as u16;
+            let checksum = match stored {
+                0 => Field::new("Checksum", "0x0000 (none)".to_string(), base + 6, 2),
+                _ => checksum_field(base + 6, stored, pseudo_checksum(3))
+            };
+            layers.push(Field::new("User Datagram Protocol", format!("{} → {}", be(payload, 0, 2), be(payload, 2, 2)), base, 8).with(vec![
+                Field::new("Source port", be(payload, 0, 2).to_string(), base, 2),
+                Field::new("Destination port", be(payload, 2, 2).to_string(), base + 2, 2),
+                Field::new("Length", be(payload, 4, 2).to_string(), base + 4, 2),
+                checksum
+            ]));
+            if payload.len() > 8 { layers.push(data_field("Data", &payload[8..], base + 8)); }
+        },
+        1 if payload.len() >= 8 => {
+            let mut children = vec![
+                Field::new("Type", format!("{} ({})", icmp_type_name(payload[0]), payload[0]), base, 1),
+                Field::new("Code", payload[1].to_string(), base + 1, 1),
+                checksum_field(base + 2, be(payload, 2, 2) as u16, pnet::util::checksum(payload, 1))
+            ];
+            match payload[0] {
+                0 | 8 | 13 | 14 => {
+                    children.push(Field::new("Identifier", be(payload, 4, 2).to_string(), base + 4, 2));
+                    children.push(Field::new("Sequence number", be(payload, 6, 2).to_string(), base + 6, 2));
+                    if payload.len() > 8 { children.push(data_field("Data", &payload[8..], base + 8)); }
+                },
+                3 | 4 | 5 | 11 | 12 => {
+                    children.push(Field::new("Rest of header", format!("0x{:08x}", be(payload, 4, 4)), base + 4, 4));
+                    let mut quoted = Vec::new();
+                    ipv4_fields(&payload[8..], base + 8, &mut quoted);
+                    children.push(Field::new("Quoted packet", String::new(), base + 8, payload.len() - 8).with(quoted));
+                },
+                _ => {
+                    children.push(Field::new("Rest of header", format!("0x{:08x}", be(payload, 4, 4)), base + 4, 4));
+                    if payload.len() > 8 { children.push(data_field("Data", &payload[8..], base + 8)); }
+                }
+            }
+            layers.push(Field::new("Internet Control Message Protocol", icmp_type_name(payload[0]), base, payload.len()).with(children));
+        },
+        2 if payload.len() >= 8 => {
+            layers.push(Field::new("Internet Group Management Protocol", format!("type 0x{:02x}", payload[0]), base, payload.len()).with(vec![
+                Field::new("Type", format!("0x{:02x}", payload[0]), base, 1),
+                Field::new("Max response code", payload[1].to_string(), base + 1, 1),
+                checksum_field(base + 2, be(payload, 2, 2) as u16, pnet::util::checksum(payload, 1)),
+                Field::new("Group", Ipv4Addr::from(be(payload, 4, 4)).to_string(), base + 4, 4)
+            ]));
+            if payload.len() > 8 { layers.push(data_field("Data", &payload[8..], base + 8)); }
+        },
+        4 => ipv4_fields(payload, base, layers),
+        132 if payload.len() >= 12 => {
+            let mut copy = payload.to_vec();
+            copy[8..12].fill(0);
+            let computed = crate::sctp::crc32c(&copy).to_le_bytes();
+            let verdict = match payload[8..12] == computed {
+                true => "correct".to_string(),
+                false => format!("incorrect, should be 0x{:08x}", u32::from_be_bytes(computed))
+            };
+            let mut children = vec![
+                Field::new("Source port", be(payload, 0, 2).to_string(), base, 2),
+                Field::new("Destination port", be(payload, 2, 2).to_string(), base + 2, 2),
+                Field::new("Verification tag", format!("0x{:08x}", be(payload, 4, 4)), base + 4, 4),
+                Field::new("Checksum", format!("0x{:08x} ({})", be(payload, 8, 4), verdict), base + 8, 4)
+            ];
+            let mut offset = 12;
+            while let Some(header) = payload.get(offset..offset + 4) {
+                let length = (be(header, 2, 2) as usize).max(4);
+                let size = length.min(payload.len() - offset);
+                children.push(Field::new("Chunk", sctp_chunk_name(header[0]), base + offset, size).with(vec![
+                    Field::new("Type", header[0].to_string(), base + offset, 1),
+                    Field::new("Flags", format!("0x{:02x}", header[1]), base + offset + 1, 1),
+                    Field::new("Length", be(header, 2, 2).to_string(), base + offset + 2, 2),
+                    data_field("Value", &payload[offset + 4..offset + size], base + offset + 4)
+                ]));
+                offset += (length + 3) & !3;
+            }
+            let summary = format!("{} → {}", be(payload, 0, 2), be(payload, 2, 2));
+            layers.push(Field::new("Stream Control Transmission Protocol", summary, base, payload.len()).with(children));
+        },
+        _ => layers.push(data_field("Data", payload, base))
+    }
+}
+
+/* Extension headers are shown as data, like in the summary. */
+fn ipv6_fields(data: &[u8], base: usize, layers: &mut Vec<Field>) {
+    if data.len() < 40 {
+        layers.push(data_field("Truncated IPv6 packet", data, base));
+        return;
+    }
+    let address = |offset: usize| Ipv6Addr::from(<[u8; 16]>::try_from(&data[offset..offset + 16]).unwrap_or([0; 16]));
+    let (source, destination) = (address(8), address(24));
+    layers.push(Field::new("Internet Protocol version 6", format!("{} → {}", source, destination), base, 40).with(vec![
+        Field::new("Version", (data[0] >> 4).to_string(), base, 1),
+        Field::new("Traffic class", (be(data, 0, 2) >> 4 & 0xff).to_string(), base, 2),
+        Field::new("Flow label", format!("0x{:05x}", be(data, 1, 3) & 0xfffff), base + 1, 3),
+        Field::new("Payload length", be(data, 4, 2).to_string(), base + 4, 2),
+        Field::new("Next header", protocol_name(data[6]), base + 6, 1),
+        Field::new("Hop limit", data[7].to_string(), base + 7, 1),
+        Field::new("Source", source.to_string(), base + 8, 16),
+        Field::new("Destination", destination.to_string(), base + 24, 16)
+    ]));
+
+    let end = (40 + be(data, 4, 2) as usize).min(data.len());
+    let message = &data[40..end];
+    let base = base + 40;
+    if data[6] != 58 || message.len() < 4 {
+        layers.push(data_field("Data", message, base));
+        return;
+    }
+
+    let computed = pnet::util::ipv6_checksum(message, 1, &[], &source, &destination, IpNextHeaderProtocols::Icmpv6);
+    let mut children = vec![
+        Field::new("Type", message[0].to_string(), base, 1),
+        Field::new("Code", message[1].to_string(), base + 1, 1),
+        checksum_field(base + 2, be(message, 2, 2) as u16, computed)
+    ];
+    let body = match message[0] {
+        128 | 129 => {
+            children.push(Field::new("Identifier", be(message, 4, 2).to_string(), base + 4, 2));
+            children.push(Field::new("Sequence number", be(message, 6, 2).to_string(), base + 6, 2));
+            8
+        },
+        133 => 8,
+        134 => 16,
+        135 | 136 => 24,
+        137 => 40,
+        _ => 4
+    }.min(message.len());
+    if matches!(message[0], 135..=137) && message.len() >= 24 {
+        children.push(Field::new("Target", Ipv6Addr::from(<[u8; 16]>::try_from(&message[8..24]).unwrap_or([0; 16])).to_string(), base + 8, 16));
+    }
+
+    if matches!(message[0], 133..=137) {
+        let mut offset = body;
+        while let Some(header) = message.get(offset..offset + 2) {
+            let length = (header[1] as usize * 8).clamp(2, message.len() - offset);
+            let name = match header[0] {
+                1 => "Source link-layer address",
+                2 => "Target link-layer address",
+                3 => "Prefix information",
+                4 => "Redirected header",
+                5 => "MTU",
+                _ => "Option"
+            };
+            children.push(data_field(name, &message[offset + 2..offset + length], base + offset));
+            offset += length;
+        }
+    } else if body < message.len() {
+        children.push(data_field("Data", &message[body..], base + body));
+    }
+    layers.push(Field::new("Internet Control Message Protocol v6", format!("type {}", message[0]), base, message.len()).with(children));
+}
+
+/* Recomputes the IP and transport lengths and checksums after bytes were edited by hand. The packet runs to the
+ * end of the frame, except that a frame of at most the Ethernet minimum, with one VLAN tag, keeps a shorter
+ * IP length as its own, the rest being padding. Tunnels as built here (GRE, IP-in-IP, VXLAN and Geneve) are
+ * fixed from the inner packet out. Transport headers of fragments are left alone, as are packets quoted by
+ * ICMP errors. */
+pub(crate) fn fix_up(frame: &mut [u8]) {
+    let padded = frame.len() <= 64;
+    fix_frame(frame, padded);
+}
+
+fn fix_frame(frame: &mut [u8], padded: bool) {
+    let offset = match link_payload(frame) {
+        Some((0x0800 | 0x86dd, payload)) if !payload.is_empty() => frame.len() - payload.len(),
+        _ => return
+    };
+    let packet = &mut frame[offset..];
+    let claimed = match packet[0] >> 4 {
+        4 => be(packet, 2, 2) as usize,
+        6 => be(packet, 4, 2) as usize + 40,
+        _ => return
+    };
+    let length = match padded && claimed >= 20 && claimed < packet.len() {
+        true => claimed,
+        false => packet.len()
+    };
+    fix_ip(&mut packet[..length]);
+}
+
+/* Checksums only, for captured frames: the packet ends at its own length, so that Ethernet padding is left
+ * alone. Packets cut short by the capture only get their IPv4 header checksum. */
+pub(crate) fn fix_checksums(frame: &mut [u8]) {
+    let offset = match link_payload(frame) {
+        Some((0x0800 | 0x86dd, payload)) if !payload.is_empty() => frame.len() - payload.len(),
+        _ => return
+    };
+    let packet = &mut frame[offset..];
+    let length = match packet[0] >> 4 {
+        4 => be(packet, 2, 2) as usize,
+        6 => be(packet, 4, 2) as usize + 40,
+        _ => return
+    };
+    match packet.len() >= length {
+        true => fix_ip(&mut packet[..length]),
+        false if packet[0] >> 4 == 4 => fix_ipv4_header(packet),
+        false => {}
+    }
+}
+
+fn fix_ip(packet: &mut [u8]) {
+    match packet.first().map(|v| v >> 4) {
+        Some(4) => fix_ipv4(packet),
+        Some(6) => fix_ipv6(packet),
+        _ => {}
+    }
+}
+
+fn fix_ipv4_header(packet: &mut [u8]) {
+    let header_length = (packet[0] & 0x0f) as usize * 4;
+    if header_length < 20 || packet.len() < header_length { return; }
+    let checksum = pnet::util::checksum(&packet[..header_length], 5);
+    packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+}
+
+fn fix_ipv4(packet: &mut [u8]) {
+    let header_length = (packet[0] & 0x0f) as usize * 4;
+    if header_length < 20 || packet.len() < header_length || packet.len() > 0xffff { return; }
+    let length = packet.len() as u16;
+    packet[2..4].copy_from_slice(&length.to_be_bytes());
+    fix_ipv4_header(packet);
+    /* More fragments or an offset: the transport header and checksum span other fragments. */
+    if be(packet, 6, 2) & 0x3fff != 0 { return; }
+
+    let source = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+    let destination = Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]);
+    let protocol = packet[9];
+    fix_tunnel(&mut packet[header_length..], protocol);
+    fix_transport(&mut packet[header_length..], protocol, |data, skipword| {
+        pnet::util::ipv4_checksum(data, skipword, &[], &source, &destination, IpNextHeaderProtocol::new(protocol))
+    });
+}
+
+fn fix_ipv6(packet: &mut [u8]) {
+    if packet.len() < 40 || packet.len() - 40 > 0xffff { return; }
+    let length = (packet.len() - 40) as u16;
+    packet[4..6].copy_from_slice(&length.to_be_bytes());
+
+    let address = |offset: usize| Ipv6Addr::from(<[u8; 16]>::try_from(&packet[offset..offset + 16]).unwrap_or([0; 16]));
+    let (source, destination) = (address(8), address(24));
+    let protocol = packet[6];
+    fix_tunnel(&mut packet[40..], protocol);
+    fix_transport(&mut packet[40..], protocol, |data, skipword| {
+        pnet::util::ipv6_checksum(data, skipword, &[], &source, &destination, IpNextHeaderProtocol::new(protocol))
+    });
+}
+
+/* Inner packet or frame of a tunnel, fixed before the outer checksums that cover it. */
+fn fix_tunnel(payload: &mut [u8], protocol: u8) {
+    match protocol {
+        4 | 41 => fix_ip(payload),
+        47 if payload.len() >= 4 => {
+            let flags = be(payload, 0, 2);
+            let header = 4 + 4 * [0x8000, 0x2000, 0x1000].iter().filter(|flag| flags & **flag != 0).count();
+            let ethertype = be(payload, 2, 2);
+            if let Some(inner) = payload.get_mut(header..) { fix_encapsulated(inner, ethertype); }
+            if flags & 0x8000 != 0 && payload.len() >= 8 {
+                let checksum = pnet::util::checksum(payload, 2);
+                payload[4..6].copy_from_slice(&checksum.to_be_bytes());
+            }
+        },
+        17 if payload.len() >= 16 => match be(payload, 2, 2) {
+            4789 => fix_frame(&mut payload[16..], false),
+            6081 => {
+                let header = 16 + (payload[8] & 0x3f) as usize * 4;
+                let ethertype = be(payload, 10, 2);
+                if let Some(inner) = payload.get_mut(header..) { fix_encapsulated(inner, ethertype); }
+            },
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+fn fix_encapsulated(inner: &mut [u8], ethertype: u32) {
+    match ethertype {
+        0x6558 => fix_frame(inner, false),
+        0x0800 | 0x86dd => fix_ip(inner),
+        _ => {}
+    }
+}
+
+fn fix_transport(transport: &mut [u8], protocol: u8, pseudo_checksum: impl Fn(&[u8], usize) -> u16) {
+    let (position, checksum) = match protocol {
+        6 if transport.len() >= 20 => (16, pseudo_checksum(transport, 8)),
+        17 if transport.len() >= 8 && transport.len() <= 0xffff => {
+            let length = transport.len() as u16;
+            transport[4..6].copy_from_slice(&length.to_be_bytes());
+            (6, match pseudo_checksum(transport, 3) { 0 => 0xffff, checksum => checksum })
+        },
+        1 | 2 if transport.len() >= 4 => (2, pnet::util::checksum(transport, 1)),
+        58 if transport.len() >= 4 => (2, pseudo_checksum(transport, 1)),
+        132 if transport.len() >= 12 => {
+            transport[8..12].fill(0);
+            let checksum = crate::sctp::crc32c(transport).to_le_bytes();
+            transport[8..12].copy_from_slice(&checksum);
+            return;
+        },
+        _ => return
+    };
+    transport[position..position + 2].copy_from_slice(&checksum.to_be_bytes());
+}
@@ -0,0 +1,324 @@
+use gtk::prelude::*;
+use rand::Rng;
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::str::FromStr;
+
+use crate::error_window::error;
+use crate::widgets::parse_entry;
+use crate::widgets::parse_hex;
+use crate::widgets::unescape;
+
+fn record_type(name: &str) -> Option<u16> {
+    let value = match name.to_ascii_uppercase().as_str() {
+        "A" => 1, "NS" => 2, "CNAME" => 5, "SOA" => 6, "PTR" => 12, "MX" => 15,
+        "TXT" => 16, "AAAA" => 28, "SRV" => 33, "OPT" => 41, "ANY" => 255,
+        other => return other.parse().ok()
+    };
+    Some(value)
+}
+
+fn next_token(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    match text.find(char::is_whitespace) {
+        Some(end) => (&text[..end], text[end..].trim_start()),
+        None => (text, "")
+    }
+}
+
+/* Encodes names, pointing to earlier occurrences of a suffix when compression is on. */
+struct Writer {
+    bytes: Vec<u8>,
+    compress: bool,
+    suffixes: HashMap<String, usize>
+}
+impl Writer {
+    fn name(&mut self, name: &str) -> Result<(), String> {
+        let name = name.trim_end_matches('.');
+        let labels: Vec<&str> = match name.is_empty() {
+            true => Vec::new(),
+            false => name.split('.').collect()
+        };
+
+        for i in 0..labels.len() {
+            let suffix = labels[i..].join(".").to_ascii_lowercase();
+            if let Some(offset) = self.suffixes.get(&suffix).filter(|_| self.compress) {
+                self.bytes.extend_from_slice(&(0xc000 | *offset as u16).to_be_bytes());
+                return Ok(());
+            }
+            if self.bytes.len() < 0x4000 { self.suffixes.insert(suffix, self.bytes.len()); }
+
+            let label = labels[i].as_bytes();
+            if label.is_empty() || label.len() > 63 { return Err("Bad DNS name ".to_owned() + name); }
+            self.bytes.push(label.len() as u8);
+            self.bytes.extend_from_slice(label);
+        }
+        self.bytes.push(0);
+        Ok(())
+    }
+    fn u16(&mut self, value: u16) { self.bytes.extend_from_slice(&value.to_be_bytes()); }
+    fn u32(&mut self, value: u32) { self.bytes.extend_from_slice(&value.to_be_bytes()); }
+
+    /* One record line: "NAME [ttl=N] [class=N] TYPE DATA", or "raw:HEX" for verbatim bytes. */
+    fn record(&mut self, line: &str) -> Result<(), String> {
+        let bad = || "Bad DNS record ".to_owned() + line;
+        if let Some(hex) = line.strip_prefix("raw:") {
+            self.bytes.extend(parse_hex(hex).ok_or_else(bad)?);
+            return Ok(());
+        }
+        let (name, rest) = next_token(line);
+
+        let (mut ttl, mut class) = (300u32, 1u16);
+        let (mut token, mut data) = next_token(rest);
+        while let Some((key, value)) = token.split_once('=') {
+            match key {
+                "ttl" => ttl = value.parse().map_err(|_| bad())?,
+                "class" => class = value.parse().map_err(|_| bad())?,
+                _ => return Err(bad())
+            }
+            (token, data) = next_token(data);
+        }
+        let record_type = record_type(token).ok_or_else(bad)?;
+
+        self.name(name)?;
+        self.u16(record_type);
+
+        /* OPT keeps the payload size in the class and the extended flags in the TTL. */
+        if record_type == 41 {
+            let (mut size, mut flags) = (4096u16, 0u32);
+            let mut options = Vec::new();
+            for field in data.split_whitespace() {
+                match field.split_once('=') {
+                    Some(("udp", value)) => size = value.parse().map_err(|_| bad())?,
+                    Some(("do", value)) => if value == "1" { flags |= 0x8000; },
+                    Some(("version", value)) => flags |= value.parse::<u8>().map_err(|_| bad())? as u32 * 0x10000,
+                    Some(("options", value)) => options = parse_hex(value).ok_or_else(bad)?,
+                    _ => return Err(bad())
+                }
+            }
+            self.u16(size);
+            self.u32(flags);
+            self.u16(options.len() as u16);
+            self.bytes.append(&mut options);
+            return Ok(());
+        }
+        self.u16(class);
+        self.u32(ttl);
+
+        let length_offset = self.bytes.len();
+        self.u16(0);
+        let fields: Vec<&str> = data.split_whitespace().collect();
+        let field = |i: usize| fields.get(i).cloned().ok_or_else(bad);
+        match record_type {
+            1 => self.bytes.extend_from_slice(&Ipv4Addr::from_str(data).map_err(|_| bad())?.octets()),
+            28 => self.bytes.extend_from_slice(&Ipv6Addr::from_str(data).map_err(|_| bad())?.octets()),
+            2 | 5 | 12 => self.name(field(0)?)?,
+            15 => {
+                self.u16(field(0)?.parse().map_err(|_| bad())?);
+                self.name(field(1)?)?;
+            },
+            6 => {
+                self.name(field(0)?)?;
+                self.name(field(1)?)?;
+                for i in 2..7 { self.u32(field(i)?.parse().map_err(|_| bad())?); }
+            },
+            /* An empty TXT still needs one empty character-string. */
+            16 if data.is_empty() => self.bytes.push(0),
+            16 => {
+                let text = unescape(data)?;
+                for chunk in text.chunks(255) {
+                    self.bytes.push(chunk.len() as u8);
+                    self.bytes.extend_from_slice(chunk);
+                }
+            },
+            _ => self.bytes.extend(parse_hex(data).ok_or_else(bad)?)
+        }
+        let length = (self.bytes.len() - length_offset - 2) as u16;
+        self.bytes[length_offset..length_offset + 2].copy_from_slice(&length.to_be_bytes());
+        Ok(())
+    }
+}
+
+pub(crate) struct DnsWidgets {
+    id: gtk::Entry,
+
+    /* QR AA TC RD RA */
+    flags: (gtk::CheckButton, gtk::CheckButton, gtk::CheckButton, gtk::CheckButton, gtk::CheckButton),
+    opcode: gtk::Entry,
+    rcode: gtk::Entry,
+    counts: (gtk::CheckButton, gtk::Entry),
+    compress: gtk::CheckButton,
+
+    name: gtk::Entry,
+    question_type: gtk::Entry,
+    question_class: gtk::Entry,
+
+    records: gtk::TextView
+}
+impl DnsWidgets {
+    pub(crate) fn new() -> Self {
+        Self {
+            id: gtk::Entry::builder().placeholder_text("Transaction ID (random)").build(),
+            flags: (gtk::CheckButton::with_label("Response"), gtk::CheckButton::with_label("AA"),
+                    gtk::CheckButton::with_label("TC"), gtk::CheckButton::builder().label("RD").active(true).build(),
+                    gtk::CheckButton::with_label("RA")),
+            opcode: gtk::Entry::builder().placeholder_text("Opcode (0)").build(),
+            rcode: gtk::Entry::builder().placeholder_text("Rcode (0)").build(),
+            counts: (gtk::CheckButton::builder().label("Auto").active(true).build(),
+                     gtk::Entry::builder().placeholder_text("QD AN NS AR").build()),
+            compress: gtk::CheckButton::builder().label("Compress names").active(true).build(),
+
+            name: gtk::Entry::builder().placeholder_text("example.com").build(),
+            question_type: gtk::Entry::builder().placeholder_text("Type (A)").build(),
+            question_class: gtk::Entry::builder().placeholder_text("Class (1)").build(),
+
+            records: gtk::TextView::builder().monospace(true).build()
+        }
+    }
+
+    pub(crate) fn prepare_ui_fields(&self) -> gtk::Box {
+        let grid = gtk::Grid::builder().margin_start(24).margin_end(24).margin_top(24).row_spacing(12)
+            .halign(gtk::Align::Center).valign(gtk::Align::Center).column_spacing(24).build();
+
+        grid.attach(&gtk::Label::builder().label("Header").halign(gtk::Align::Start).build(), 0, 0, 1, 1);
+        grid.attach(&self.id, 1, 0, 1, 1);
+        grid.attach(&self.opcode, 2, 0, 1, 1);
+        grid.attach(&self.rcode, 3, 0, 1, 1);
+
+        let flags_box = gtk::Box::builder().orientation(gtk::Orientation::Horizontal).spacing(6).build();
+        flags_box.append(&self.flags.0); flags_box.append(&self.flags.1); flags_box.append(&self.flags.2);
+        flags_box.append(&self.flags.3); flags_box.append(&self.flags.4);
+        grid.attach(&flags_box, 1, 1, 2, 1);
+        grid.attach(&self.compress, 3, 1, 1, 1);
+
+        grid.attach(&gtk::Label::builder().label("Counts").halign(gtk::Align::Start).build(), 0, 2, 1, 1);
+        grid.attach(&self.counts.0, 1, 2, 1, 1);
+        grid.attach(&self.counts.1, 2, 2, 1, 1);
+
+        grid.attach(&gtk::Label::builder().label("Question").halign(gtk::Align::Start).build(), 0, 3, 1, 1);
+        grid.attach(&self.name, 1, 3, 1, 1);
+        grid.attach(&self.question_type, 2, 3, 1, 1);
+        grid.attach(&self.question_class, 3, 3, 1, 1);
+
+        let hint = "One record per line: SECTION NAME [ttl=N] [class=N] TYPE DATA, section is an, ns or ar.\n\
+                    an example.com A 192.0.2.1 | ar . OPT udp=1232 do=1 | an example.com 65280 deadbeef | ns raw:c00c...";
+        let scrolled_window = gtk::ScrolledWindow::builder().child(&self.records).min_content_height(120).min_content_width(600).build();
+
+        let main_box = gtk::Box::builder().orientation(gtk::Orientation::Vertical).spacing(12).margin_bottom(12).build();
+        main_box.append(&grid);
+        main_box.append(&gtk::Label::builder().label(hint).halign(gtk::Align::Start).margin_start(24).build());
+        main_box.append(&gtk::Frame::builder().label("Records").child(&scrolled_window).margin_start(24).margin_end(24).build());
+        main_box
+    }
+
+    pub(crate) fn build_message(&self) -> Option<Vec<u8>> {
+        match self.message() {
+            Ok(message) => message,
+            Err(what) => { error(&what); None }
+        }
+    }
+
+    fn message(&self) -> Result<Option<Vec<u8>>, String> {
+        let id = match parse_entry(&self.id, rand::thread_rng().gen::<u16>(), "DNS transaction ID") {
+            Some(id) => id,
+            None => return Ok(None)
+        };
+        let opcode = match parse_entry(&self.opcode, 0u16, "DNS opcode") {
+            Some(opcode) => opcode & 0x0f,
+            None => return Ok(None)
+        };
+        let rcode = match parse_entry(&self.rcode, 0u16, "DNS rcode") {
+            Some(rcode) => rcode & 0x0f,
+            None => return Ok(None)
+        };
+
+        let mut flags = opcode << 11 | rcode;
+        if self.flags.0.is_active() { flags |= 0x8000; }
+        if self.flags.1.is_active() { flags |= 0x0400; }
+        if self.flags.2.is_active() { flags |= 0x0200; }
+        if self.flags.3.is_active() { flags |= 0x0100; }
+        if self.flags.4.is_active() { flags |= 0x0080; }
+
+        let mut writer = Writer { bytes: Vec::new(), compress: self.compress.is_active(), suffixes: HashMap::new() };
+        writer.u16(id);
+        writer.u16(flags);
+        writer.bytes.extend_from_slice(&[0; 8]);
+
+        let mut counts = [0u16; 4];
+        if !self.name.text().is_empty() {
+            let question_type = match self.question_type.text().as_str() {
+                "" => 1,
+                text => record_type(text).ok_or("Bad DNS question type")?
+            };
+            let question_class = match parse_entry(&self.question_class, 1u16, "DNS question class") {
+                Some(class) => class,
+                None => return Ok(None)
+            };
+            writer.name(&self.name.text())?;
+            writer.u16(question_type);
+            writer.u16(question_class);
+            counts[0] = 1;
+        }
+
+        /* Records are written in section order whatever order the lines come in. */
+        let buffer = self.records.buffer();
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+        for (index, section) in ["an", "ns", "ar"].iter().enumerate() {
+            for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+                let (name, rest) = next_token(line);
+                if !["an", "ns", "ar"].contains(&name) { return Err("Unknown DNS section in ".to_owned() + line); }
+                if name != *section { continue; }
+                writer.record(rest)?;
+                counts[index + 1] += 1;
+            }
+        }
+
+        if !self.counts.0.is_active() {
+            let values: Vec<u16> = self.counts.1.text().split_whitespace().filter_map(|v| v.parse().ok()).collect();
+            if values.len() != 4 { return Err("DNS counts should be four numbers: QD AN NS AR".to_string()); }
+            counts.copy_from_slice(&values);
+        }
+        for (i, count) in counts.iter().enumerate() {
+            writer.bytes[4 + i * 2..6 + i * 2].copy_from_slice(&count.to_be_bytes());
+        }
+
+        Ok(Some(writer.bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn writer(compress: bool) -> Writer {
+        Writer { bytes: vec![0; 12], compress, suffixes: HashMap::new() }
+    }
+
+    #[test]
+    fn names_point_to_earlier_suffixes() {
+        let mut writer = writer(true);
+        writer.name("www.example.com").unwrap();
+        writer.name("mail.Example.COM.").unwrap();
+        writer.name("www.example.com").unwrap();
+        assert_eq!(writer.bytes[12..], [3, b'w', b'w', b'w', 7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0,
+            4, b'm', b'a', b'i', b'l', 0xc0, 16,
+            0xc0, 12]);
+    }
+
+    #[test]
+    fn names_are_written_whole_without_compression() {
+        let mut writer = writer(false);
+        writer.name("a.example").unwrap();
+        writer.name("b.example").unwrap();
+        writer.name(".").unwrap();
+        assert_eq!(writer.bytes[12..], [1, b'a', 7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0,
+            1, b'b', 7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0, 0]);
+    }
+
+    #[test]
+    fn bad_labels() {
+        assert!(writer(true).name("a..b").is_err());
+        assert!(writer(true).name(&"x".repeat(64)).is_err());
+    }
+}
@@ -0,0 +1,301 @@
+use gtk::prelude::*;
+use pnet::util::MacAddr;
+
+use crate::error_window::error;
+use crate::widgets::parse_entry;
+use crate::widgets::parse_number;
+use crate::widgets::escape;
+use crate::widgets::unescape;
+
+pub(crate) const CUSTOMER_TPID: u16 = 0x8100;
+pub(crate) const SERVICE_TPID: u16 = 0x88a8;
+
+/* One 802.1Q/802.1ad tag, written as "VID [pcp=N] [dei=1] [tpid=0x88a8]". */
+struct VlanTag {
+    tpid: Option<u16>,
+    pcp: u8,
+    dei: bool,
+    vid: u16
+}
+impl VlanTag {
+    fn parse(text: &str) -> Result<VlanTag, String> {
+        let mut fields = text.split_whitespace();
+        let bad = || "Bad VLAN tag ".to_owned() + text;
+
+        let vid = fields.next().and_then(parse_number).filter(|vid| *vid < 4096).ok_or_else(bad)?;
+        let mut tag = VlanTag { tpid: None, pcp: 0, dei: false, vid };
+        for field in fields {
+            let (name, value) = field.split_once('=').ok_or_else(bad)?;
+            match name {
+                "pcp" => tag.pcp = value.parse().ok().filter(|pcp| *pcp < 8).ok_or_else(bad)?,
+                "dei" => tag.dei = matches!(value, "1" | "true"),
+                "tpid" => tag.tpid = Some(parse_number(value).ok_or_else(bad)?),
+                _ => return Err("Unknown VLAN tag field ".to_owned() + name)
+            }
+        }
+        Ok(tag)
+    }
+}
+
+/* One MPLS label stack entry, written as "LABEL [tc=N] [ttl=N] [s=0|1]". */
+struct MplsLabel {
+    label: u32,
+    tc: u8,
+    ttl: u8,
+    bottom: Option<bool>
+}
+impl MplsLabel {
+    fn parse(text: &str) -> Result<MplsLabel, String> {
+        let mut fields = text.split_whitespace();
+        let bad = || "Bad MPLS label ".to_owned() + text;
+
+        let label = fields.next().and_then(|v| v.parse::<u32>().ok()).filter(|label| *label < 1 << 20).ok_or_else(bad)?;
+        let mut entry = MplsLabel { label, tc: 0, ttl: 64, bottom: None };
+        for field in fields {
+            let (name, value) = field.split_once('=').ok_or_else(bad)?;
+            match name {
+                "tc" => entry.tc = value.parse().ok().filter(|tc| *tc < 8).ok_or_else(bad)?,
+                "ttl" => entry.ttl = value.parse().map_err(|_| bad())?,
+                "s" => entry.bottom = Some(matches!(value, "1" | "true")),
+                _ => return Err("Unknown MPLS label field ".to_owned() + name)
+            }
+        }
+        Ok(entry)
+    }
+}
+
+pub(crate) struct EthernetWidgets {
+    vlan: (gtk::CheckButton, gtk::Entry),
+    multicast: gtk::CheckButton,
+    mpls: (gtk::CheckButton, gtk::Entry, gtk::DropDown),
+
+    raw: gtk::CheckButton,
+    raw_kind: gtk::DropDown,
+    ethertype: gtk::Entry,
+    oui: gtk::Entry,
+    length: (gtk::CheckButton, gtk::Entry),
+    frame_size: gtk::Entry,
+    payload: gtk::Entry
+}
+impl EthernetWidgets {
+    pub(crate) fn new() -> Self {
+        Self {
+            vlan: (gtk::CheckButton::with_label("VLAN tags"),
+                   gtk::Entry::builder().placeholder_text("Outer first: 100 pcp=3 tpid=0x88a8, 200 dei=1").width_chars(40).build()),
+
+            multicast: gtk::CheckButton::with_label("Multicast MAC from IP"),
+            mpls: (gtk::CheckButton::with_label("MPLS labels"),
+                   gtk::Entry::builder().placeholder_text("Top first: 16 tc=5 ttl=1, 100").width_chars(32).build(),
+                   gtk::DropDown::from_strings(&["Unicast (0x8847)", "Multicast (0x8848)"])),
+
+            raw: gtk::CheckButton::with_label("Raw frame"),
+            raw_kind: gtk::DropDown::from_strings(&["Ethernet II", "802.3 LLC/SNAP"]),
+            ethertype: gtk::Entry::builder().placeholder_text("EtherType / PID (0x88b5)").build(),
+            oui: gtk::Entry::builder().placeholder_text("SNAP OUI (00.00.00)").build(),
+            length: (gtk::CheckButton::builder().label("Auto").active(true).build(), gtk::Entry::builder().placeholder_text("802.3 length").build()),
+            frame_size: gtk::Entry::builder().placeholder_text("Frame size, bytes").build(),
+            payload: gtk::Entry::builder().placeholder_text("Payload, \\xNN escapes allowed").hexpand(true).build()
+        }
+    }
+
+    pub(crate) fn prepare_ui_fields(&self) -> gtk::Frame {
+        let main_box = gtk::Box::builder().orientation(gtk::Orientation::Vertical).halign(gtk::Align::Center)
+            .margin_start(24).margin_end(24).margin_top(6).margin_bottom(12).valign(gtk::Align::Center).spacing(12).build();
+
+        let vlan_box = gtk::Box::builder().orientation(gtk::Orientation::Horizontal).spacing(24).build();
+        vlan_box.append(&self.vlan.0);
+        vlan_box.append(&self.vlan.1);
+        vlan_box.append(&self.multicast);
+        vlan_box.append(&self.mpls.0);
+        vlan_box.append(&self.mpls.1);
+        vlan_box.append(&self.mpls.2);
+        main_box.append(&vlan_box);
+
+        let raw_grid = gtk::Grid::builder().margin_start(6).margin_end(6).margin_top(6).margin_bottom(6)
+            .row_spacing(6).column_spacing(12).build();
+        raw_grid.attach(&self.raw, 0, 0, 1, 1);
+        raw_grid.attach(&self.raw_kind, 1, 0, 1, 1);
+        raw_grid.attach(&self.ethertype, 2, 0, 1, 1);
+        raw_grid.attach(&self.oui, 3, 0, 1, 1);
+        raw_grid.attach(&self.length.0, 4, 0, 1, 1);
+        raw_grid.attach(&self.length.1, 5, 0, 1, 1);
+        raw_grid.attach(&self.frame_size, 6, 0, 1, 1);
+        raw_grid.attach(&self.payload, 0, 1, 7, 1);
+        main_box.append(&gtk::Frame::builder().label("Raw layer 2 frame, bypasses the IP builder").child(&raw_grid).build());
+
+        gtk::Frame::builder().label("Ethernet").child(&main_box).build()
+    }
+
+    pub(crate) fn is_raw(&self) -> bool { self.raw.is_active() }
+
+    /* Tags and labels of a stored frame. Anything but IPv4 goes to the raw frame fields. */
+    pub(crate) fn load(&self, frame: &[u8]) {
+        let mut offset = 12;
+        let mut tags = Vec::new();
+        while let Some(tag) = frame.get(offset..offset + 4) {
+            let tpid = u16::from_be_bytes([tag[0], tag[1]]);
+            if !matches!(tpid, CUSTOMER_TPID | SERVICE_TPID | 0x9100) { break; }
+            tags.push(format!("{} pcp={} dei={} tpid=0x{:04x}", u16::from_be_bytes([tag[2] & 0x0f, tag[3]]), tag[2] >> 5, tag[2] >> 4 & 1, tpid));
+            offset += 4;
+        }
+        self.vlan.0.set_active(!tags.is_empty());
+        self.vlan.1.set_text(&tags.join(", "));
+
+        let ethertype = frame.get(offset..offset + 2).map(|v| u16::from_be_bytes([v[0], v[1]])).unwrap_or(0);
+        offset += 2;
+        let mut labels = Vec::new();
+        if matches!(ethertype, 0x8847 | 0x8848) {
+            while let Some(entry) = frame.get(offset..offset + 4) {
+                let entry = u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]);
+                labels.push(format!("{} tc={} ttl={}", entry >> 12, entry >> 9 & 7, entry & 0xff));
+                offset += 4;
+                if entry & 0x100 != 0 { break; }
+            }
+            self.mpls.2.set_selected(if ethertype == 0x8848 { 1 } else { 0 });
+        }
+        self.mpls.0.set_active(!labels.is_empty());
+        self.mpls.1.set_text(&labels.join(", "));
+
+        let payload = frame.get(offset..).unwrap_or(&[]);
+        let ip = match labels.is_empty() {
+            true => ethertype == 0x0800,
+            false => payload.first().map(|v| v >> 4) == Some(4)
+        };
+        self.raw.set_active(!ip);
+        if ip { return; }
+
+        /* Labels over something else are kept in the raw payload. Plain 802.3 frames are sent as an
+         * Ethernet II frame with the length in place of the EtherType. */
+        let payload = frame.get(12 + 4 * tags.len() + 2..).unwrap_or(&[]);
+        self.mpls.0.set_active(false);
+        self.length.0.set_active(true);
+        self.frame_size.set_text("");
+        match ethertype <= 1500 && payload.len() >= 8 && payload[..3] == [0xaa, 0xaa, 0x03] {
+            true => {
+                self.raw_kind.set_selected(1);
+                self.oui.set_text(&payload[3..6].iter().map(|v| format!("{:02x}", v)).collect::<Vec<_>>().join("."));
+                self.ethertype.set_text(&format!("0x{:04x}", u16::from_be_bytes([payload[6], payload[7]])));
+                self.payload.set_text(&escape(&payload[8..]));
+            },
+            false => {
+                self.raw_kind.set_selected(0);
+                self.oui.set_text("");
+                self.ethertype.set_text(&format!("0x{:04x}", ethertype));
+                self.payload.set_text(&escape(payload));
+            }
+        }
+    }
+
+    /* 01:00:5e followed by the low 23 bits of a multicast IPv4 destination, 33:33 and the low 32 bits for IPv6. */
+    pub(crate) fn multicast_mac(&self, ip: &[u8]) -> Option<MacAddr> {
+        if !self.multicast.is_active() || ip.is_empty() { return None; }
+        match ip[0] >> 4 {
+            4 if ip.len() >= 20 && ip[16] & 0xf0 == 0xe0 => Some(MacAddr::new(0x01, 0x00, 0x5e, ip[17] & 0x7f, ip[18], ip[19])),
+            6 if ip.len() >= 40 && ip[24] == 0xff => Some(MacAddr::new(0x33, 0x33, ip[36], ip[37], ip[38], ip[39])),
+            _ => None
+        }
+    }
+
+    /* Tag stack placed between the MAC addresses and the EtherType. Without an explicit TPID the outer tags
+     * of a stack are 802.1ad service tags and the innermost one is an 802.1Q customer tag. */
+    pub(crate) fn tags(&self) -> Option<Vec<u8>> {
+        if !self.vlan.0.is_active() { return Some(Vec::new()); }
+
+        let text = self.vlan.1.text();
+        let tags = match text.split(',').map(VlanTag::parse).collect::<Result<Vec<_>, _>>() {
+            Ok(tags) => tags,
+            Err(what) => { error(&what); return None; }
+        };
+
+        let mut result = Vec::new();
+        for (i, tag) in tags.iter().enumerate() {
+            let tpid = tag.tpid.unwrap_or(if i + 1 < tags.len() { SERVICE_TPID } else { CUSTOMER_TPID });
+            let control = (tag.pcp as u16) << 13 | (tag.dei as u16) << 12 | tag.vid;
+            result.extend_from_slice(&tpid.to_be_bytes());
+            result.extend_from_slice(&control.to_be_bytes());
+        }
+        Some(result)
+    }
+
+    /* Destination and source addresses, the tag stack and the given EtherType or 802.3 length. */
+    pub(crate) fn header(&self, addresses: (MacAddr, MacAddr), ethertype: u16) -> Option<Vec<u8>> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&addresses.1.octets());
+        header.extend_from_slice(&addresses.0.octets());
+        header.extend(self.tags()?);
+        header.extend_from_slice(&ethertype.to_be_bytes());
+        Some(header)
+    }
+
+    /* Header in front of an IP packet, with the MPLS label stack if enabled. The bottom of stack bit is set
+     * on the last label unless given explicitly. */
+    pub(crate) fn ip_header(&self, addresses: (MacAddr, MacAddr), ethertype: u16) -> Option<Vec<u8>> {
+        if !self.mpls.0.is_active() { return self.header(addresses, ethertype); }
+
+        let text = self.mpls.1.text();
+        let labels = match text.split(',').map(MplsLabel::parse).collect::<Result<Vec<_>, _>>() {
+            Ok(labels) => labels,
+            Err(what) => { error(&what); return None; }
+        };
+
+        let ethertype = match self.mpls.2.selected() {
+            0 => 0x8847,
+            _ => 0x8848
+        };
+        let mut header = self.header(addresses, ethertype)?;
+        for (i, label) in labels.iter().enumerate() {
+            let bottom = label.bottom.unwrap_or(i + 1 == labels.len());
+            let entry = label.label << 12 | (label.tc as u32) << 9 | (bottom as u32) << 8 | label.ttl as u32;
+            header.extend_from_slice(&entry.to_be_bytes());
+        }
+        Some(header)
+    }
+
+    /* The frame size pads with zeros or truncates, so both jumbo frames and runts can be sent. */
+    pub(crate) fn raw_frame(&self, addresses: (MacAddr, MacAddr)) -> Option<Vec<u8>> {
+        let ethertype = match parse_number(&self.ethertype.text()) {
+            Some(value) => value,
+            None if self.ethertype.text().is_empty() => 0x88b5,
+            None => { error("Bad EtherType value"); return None; }
+        };
+        let mut payload = match unescape(&self.payload.text()) {
+            Ok(payload) => payload,
+            Err(what) => { error(&what); return None; }
+        };
+
+        let mut frame = match self.raw_kind.selected() {
+            0 => {
+                let mut frame = self.header(addresses, ethertype)?;
+                frame.append(&mut payload);
+                frame
+            },
+            _ => {
+                let oui = match self.oui.text().as_str() {
+                    "" => [0u8; 3],
+                    text => match text.split(['.', ':']).map(|v| u8::from_str_radix(v, 16)).collect::<Result<Vec<_>, _>>() {
+                        Ok(bytes) if bytes.len() == 3 => [bytes[0], bytes[1], bytes[2]],
+                        _ => { error("Bad SNAP OUI value"); return None; }
+                    }
+                };
+                let mut llc = vec![0xaa, 0xaa, 0x03, oui[0], oui[1], oui[2]];
+                llc.extend_from_slice(&ethertype.to_be_bytes());
+                llc.append(&mut payload);
+
+                let length = match self.length.0.is_active() {
+                    true => llc.len() as u16,
+                    false => match self.length.1.text().parse::<u16>() {
+                        Ok(value) => value,
+                        Err(_) => { error("Bad 802.3 length value"); return None; }
+                    }
+                };
+                let mut frame = self.header(addresses, length)?;
+                frame.append(&mut llc);
+                frame
+            }
+        };
+
+        let size = parse_entry(&self.frame_size, frame.len(), "frame size")?;
+        frame.resize(size, 0);
+        Some(frame)
+    }
+}
@@ -0,0 +1,214 @@
+use gtk::prelude::*;
+use rand::seq::SliceRandom;
+use pnet::packet::Packet;
+use pnet::packet::ipv4::Ipv4Flags;
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::ipv4::MutableIpv4Packet;
+
+use crate::error_window::error;
+use crate::widgets::parse_entry;
+
+struct FragmentOptions {
+    size: usize,
+    overlap: usize,
+    tiny_first: bool,
+    out_of_order: bool,
+    duplicate: bool,
+    drop_last: bool
+}
+
+pub(crate) struct FragmentationWidgets {
+    enabled: gtk::CheckButton,
+    size_kind: gtk::DropDown,
+    size: gtk::Entry,
+    overlap: gtk::Entry,
+
+    /* Tiny first, out of order, duplicate, drop last */
+    evasions: (gtk::CheckButton, gtk::CheckButton, gtk::CheckButton, gtk::CheckButton)
+}
+impl FragmentationWidgets {
+    pub(crate) fn is_enabled(&self) -> bool { self.enabled.is_active() }
+    pub(crate) fn enabled_button(&self) -> &gtk::CheckButton { &self.enabled }
+
+    pub(crate) fn new() -> Self {
+        Self {
+            enabled: gtk::CheckButton::with_label("Fragment"),
+            size_kind: gtk::DropDown::from_strings(&["MTU", "Fragment size"]),
+            size: gtk::Entry::builder().placeholder_text("1500").build(),
+            overlap: gtk::Entry::builder().placeholder_text("Overlap bytes").build(),
+            evasions: (gtk::CheckButton::with_label("Tiny first"), gtk::CheckButton::with_label("Out of order"),
+                       gtk::CheckButton::with_label("Duplicate"), gtk::CheckButton::with_label("Drop last"))
+        }
+    }
+
+    pub(crate) fn prepare_ui_fields(&self) -> gtk::Frame {
+        let main_box = gtk::Box::builder().orientation(gtk::Orientation::Horizontal).halign(gtk::Align::Center)
+            .margin_start(24).margin_end(24).margin_top(6).margin_bottom(12).valign(gtk::Align::Center).spacing(24).build();
+
+        main_box.append(&self.enabled);
+        main_box.append(&self.size_kind);
+        main_box.append(&self.size);
+        main_box.append(&self.overlap);
+
+        let evasions_box = gtk::Box::builder().orientation(gtk::Orientation::Horizontal).spacing(6).margin_start(6).margin_end(6).build();
+        evasions_box.append(&self.evasions.0); evasions_box.append(&self.evasions.1);
+        evasions_box.append(&self.evasions.2); evasions_box.append(&self.evasions.3);
+        main_box.append(&gtk::Frame::builder().label("Evasions").child(&evasions_box).build());
+
+        gtk::Frame::builder().label("IP fragmentation").child(&main_box).build()
+    }
+
+    /* Returns the datagram itself when fragmentation is off. */
+    pub(crate) fn fragment(&self, datagram: &[u8]) -> Option<Vec<Vec<u8>>> {
+        if !self.enabled.is_active() { return Some(vec![datagram.to_vec()]); }
+
+        let header_length = Ipv4Packet::new(datagram)?.get_header_length() as usize * 4;
+        if header_length < 20 { error("Fragmentation needs an IP header length of at least 5"); return None; }
+        let size = parse_entry(&self.size, 1500usize, "fragment size")?;
+        let size = match self.size_kind.selected() {
+            0 => size.saturating_sub(header_length),
+            _ => size
+        } / 8 * 8;
+        if size == 0 { error("Fragment size must allow at least 8 bytes of data"); return None; }
+
+        let overlap = parse_entry(&self.overlap, 0usize, "fragment overlap")? / 8 * 8;
+        if overlap >= size { error("Fragment overlap must be smaller than the fragment size"); return None; }
+
+        Some(fragment(datagram, &FragmentOptions {
+            size, overlap,
+            tiny_first: self.evasions.0.is_active(),
+            out_of_order: self.evasions.1.is_active(),
+            duplicate: self.evasions.2.is_active(),
+            drop_last: self.evasions.3.is_active()
+        }))
+    }
+}
+
+fn fragment(datagram: &[u8], options: &FragmentOptions) -> Vec<Vec<u8>> {
+    let packet = Ipv4Packet::new(datagram).unwrap();
+    let header_length = (packet.get_header_length() as usize * 4).min(datagram.len());
+    let header = &datagram[..header_length];
+    let payload = &datagram[header_length..];
+
+    /* A datagram that is a fragment itself keeps its offset and its More Fragments flag on the last piece. */
+    let base_offset = packet.get_fragment_offset() as usize * 8;
+    let more_fragments = packet.get_flags() & Ipv4Flags::MoreFragments != 0;
+    let later_header = copied_options_header(header);
+
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while start < payload.len() || ranges.is_empty() {
+        let length = match options.tiny_first && ranges.is_empty() {
+            true => 8,
+            false => options.size
+        };
+        let end = (start + length).min(payload.len());
+        ranges.push((start, end));
+        if end == payload.len() { break; }
+        start = end - if ranges.len() > 1 || !options.tiny_first { options.overlap } else { 0 };
+    }
+
+    let count = ranges.len();
+    let mut fragments: Vec<Vec<u8>> = ranges.iter().enumerate().map(|(i, (start, end))| {
+        let header = match i {
+            0 => header,
+            _ => &later_header
+        };
+        let mut bytes = header.to_vec();
+        bytes.extend_from_slice(&payload[*start..*end]);
+
+        let mut fragment = MutableIpv4Packet::new(&mut bytes).unwrap();
+        fragment.set_header_length((header.len() / 4) as u8);
+        fragment.set_total_length((header.len() + end - start) as u16);
+        fragment.set_fragment_offset(((base_offset + start) / 8) as u16);
+        let mut flags = fragment.get_flags() & !Ipv4Flags::MoreFragments;
+        if i + 1 < count || more_fragments { flags |= Ipv4Flags::MoreFragments; }
+        fragment.set_flags(flags);
+        fragment.set_checksum(pnet::packet::ipv4::checksum(&fragment.to_immutable()));
+        Vec::from(fragment.packet())
+    }).collect();
+
+    if options.drop_last && fragments.len() > 1 { fragments.pop(); }
+    if options.out_of_order && fragments.len() > 1 {
+        let original = fragments.clone();
+        fragments.shuffle(&mut rand::thread_rng());
+        if fragments == original { fragments.reverse(); }
+    }
+    if options.duplicate {
+        fragments = fragments.into_iter().flat_map(|fragment| vec![fragment.clone(), fragment]).collect();
+    }
+
+    fragments
+}
+
+/* Header for all fragments but the first: only options with the copied flag set are repeated. */
+fn copied_options_header(header: &[u8]) -> Vec<u8> {
+    let mut result = header[..header.len().min(20)].to_vec();
+
+    let mut i = 20;
+    while i < header.len() {
+        let option = header[i];
+        match option {
+            0 => break,
+            1 => { i += 1; continue; }
+            _ => {}
+        }
+        let length = match header.get(i + 1) {
+            Some(length) if *length >= 2 => *length as usize,
+            _ => break
+        };
+        if option & 0x80 != 0 { result.extend_from_slice(&header[i..(i + length).min(header.len())]); }
+        i += length;
+    }
+
+    while result.len() & 3 != 0 { result.push(0); }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datagram(payload: usize, flags: u8) -> Vec<u8> {
+        let mut bytes = vec![0x45, 0, 0, 0, 0, 1, flags, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2];
+        bytes.extend((0..payload).map(|i| i as u8));
+        let length = bytes.len() as u16;
+        bytes[2..4].copy_from_slice(&length.to_be_bytes());
+        bytes
+    }
+
+    fn options(size: usize) -> FragmentOptions {
+        FragmentOptions { size, overlap: 0, tiny_first: false, out_of_order: false, duplicate: false, drop_last: false }
+    }
+
+    /* Offset in bytes, More Fragments and payload length of every fragment. */
+    fn layout(fragments: &[Vec<u8>]) -> Vec<(usize, bool, usize)> {
+        fragments.iter().map(|fragment| {
+            let packet = Ipv4Packet::new(fragment).unwrap();
+            assert_eq!(packet.get_total_length() as usize, fragment.len());
+            assert_eq!(packet.get_checksum(), pnet::packet::ipv4::checksum(&packet));
+            (packet.get_fragment_offset() as usize * 8, packet.get_flags() & Ipv4Flags::MoreFragments != 0, fragment.len() - 20)
+        }).collect()
+    }
+
+    #[test]
+    fn offsets_and_flags() {
+        assert_eq!(layout(&fragment(&datagram(100, 0), &options(40))), [(0, true, 40), (40, true, 40), (80, false, 20)]);
+        assert_eq!(layout(&fragment(&datagram(16, 0), &options(40))), [(0, false, 16)]);
+    }
+
+    #[test]
+    fn overlap_and_tiny_first() {
+        let overlapping = FragmentOptions { overlap: 8, ..options(24) };
+        assert_eq!(layout(&fragment(&datagram(48, 0), &overlapping)), [(0, true, 24), (16, true, 24), (32, false, 16)]);
+        let tiny = FragmentOptions { tiny_first: true, ..options(24) };
+        assert_eq!(layout(&fragment(&datagram(40, 0), &tiny)), [(0, true, 8), (8, true, 24), (32, false, 8)]);
+    }
+
+    #[test]
+    fn fragments_of_a_fragment() {
+        let mut first = datagram(32, 0x20);
+        first[7] = 2;
+        assert_eq!(layout(&fragment(&first, &options(16))), [(16, true, 16), (32, true, 16)]);
+    }
+}
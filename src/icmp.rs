@@ -1,32 +1,49 @@
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::time::Duration;
 use gtk::prelude::*;
 use pnet::packet::Packet;
 use pnet::packet::icmp::IcmpTypes;
 use pnet::packet::icmp::IcmpCode;
 use pnet::packet::icmp::MutableIcmpPacket;
 use pnet::packet::ip::IpNextHeaderProtocol;
+use pnet::packet::ip::IpNextHeaderProtocols;
 use crate::database::Database;
 
+use crate::channel;
 use crate::error_window::error;
-use crate::show_packet::show;
+use crate::ping;
+use crate::ping::PingParameters;
+use crate::template::FrameTemplate;
 use crate::widgets::MainWindowWidgets;
+use crate::widgets::entry_data;
+use crate::widgets::escape;
+use crate::widgets::parse_entry;
 
 pub(crate) struct IcmpOptions {
     type_dropdown: gtk::DropDown,
     code_entry: gtk::Entry,
     checksum_entry: gtk::Entry,
+    identifier_entry: gtk::Entry,
+    sequence_entry: gtk::Entry,
     data_entry: gtk::Entry,
+    escapes: gtk::CheckButton,
+
+    count_entry: gtk::Entry,
+    interval_entry: gtk::Entry,
+    timeout_entry: gtk::Entry
 }
 impl IcmpOptions {
-    pub(crate) fn show_window(widgets: Rc<RefCell<MainWindowWidgets>>, database: Rc<RefCell<Database>>) {
+    pub(crate) fn show_window(widgets: Rc<RefCell<MainWindowWidgets>>, database: Rc<RefCell<Database>>, send_now: bool) {
         let icmp_widgets = IcmpOptions::new();
         let dialog = gtk::Dialog::with_buttons(
             Some("ICMP options"),
             Some(&gtk::Window::new()),
             gtk::DialogFlags::USE_HEADER_BAR,
-            &[("Ok", gtk::ResponseType::Ok), ("Cancel", gtk::ResponseType::Cancel)]);
+            &[("Ok", gtk::ResponseType::Ok), ("Ping", gtk::ResponseType::Apply), ("Cancel", gtk::ResponseType::Cancel)]);
         dialog.content_area().append(&icmp_widgets.generate_ui());
+        if let Some(message) = widgets.borrow_mut().take_loaded(1) { icmp_widgets.load(&message); }
+        dialog.set_response_sensitive(gtk::ResponseType::Apply, widgets.borrow().plain_ip());
 
         dialog.connect_response(move |dialog, response| {
             match response {
@@ -36,14 +53,27 @@ impl IcmpOptions {
                         None => { dialog.close(); return; }
                     };
 
-                    show("ICMP packet", &icmp_packet);
+                    MainWindowWidgets::build_ip_packet(widgets.clone(), icmp_packet, IpNextHeaderProtocol::new(1), database.clone(), "ICMP", send_now);
+                    dialog.close();
+                },
+                gtk::ResponseType::Apply => {
+                    let parameters = match icmp_widgets.ping_parameters() {
+                        Some(value) => value,
+                        None => return
+                    };
 
-                    let ip_packet = match widgets.borrow().ip_widgets.build_packet(IpNextHeaderProtocol::new(1), &icmp_packet) {
+                    let template = match FrameTemplate::new(&widgets.borrow(), IpNextHeaderProtocols::Icmp) {
                         Some(value) => value,
-                        None => { dialog.close(); return; }
+                        None => return
                     };
 
-                    MainWindowWidgets::build_frame(widgets.clone(), &ip_packet, database.clone(), "ICMP");
+                    let iface = widgets.borrow().get_active_interface();
+                    let channel = match channel::open(&iface, widgets.borrow().is_offline(), Some(channel::POLL_INTERVAL)) {
+                        Ok(channel) => channel,
+                        Err(what) => { error(&what); return; }
+                    };
+
+                    ping::start(template, channel, parameters);
                     dialog.close();
                 },
                 gtk::ResponseType::Cancel => {
@@ -59,9 +89,22 @@ impl IcmpOptions {
     fn generate_ui(&self) -> gtk::Box {
         let icmp_grid = self.prepare_ui_fields();
 
+        let ping_grid = gtk::Grid::builder().margin_start(12).margin_end(12).margin_top(12).margin_bottom(12).row_spacing(12)
+            .halign(gtk::Align::Center).valign(gtk::Align::Center).column_spacing(24).build();
+
+        ping_grid.attach(&gtk::Label::builder().label("Count:").halign(gtk::Align::Start).build(), 0, 0, 1, 1);
+        ping_grid.attach(&self.count_entry, 1, 0, 1, 1);
+
+        ping_grid.attach(&gtk::Label::builder().label("Interval (ms):").halign(gtk::Align::Start).build(), 2, 0, 1, 1);
+        ping_grid.attach(&self.interval_entry, 3, 0, 1, 1);
+
+        ping_grid.attach(&gtk::Label::builder().label("Timeout (ms):").halign(gtk::Align::Start).build(), 0, 1, 1, 1);
+        ping_grid.attach(&self.timeout_entry, 1, 1, 1, 1);
+
         let main_box = gtk::Box::builder().orientation(gtk::Orientation::Vertical)
-            .halign(gtk::Align::Center).valign(gtk::Align::Center).spacing(24).build();
+            .halign(gtk::Align::Center).valign(gtk::Align::Center).spacing(24).margin_bottom(24).build();
         main_box.append(&icmp_grid);
+        main_box.append(&gtk::Frame::builder().label("Ping").child(&ping_grid).margin_start(24).margin_end(24).build());
 
         main_box
     }
@@ -80,6 +123,13 @@ impl IcmpOptions {
 
         icmp_grid.attach(&gtk::Label::builder().label("Data:").halign(gtk::Align::Start).build(), 2, 1, 1, 1);
         icmp_grid.attach(&self.data_entry, 3, 1, 1, 1);
+        icmp_grid.attach(&self.escapes, 4, 1, 1, 1);
+
+        icmp_grid.attach(&gtk::Label::builder().label("Identifier:").halign(gtk::Align::Start).build(), 0, 2, 1, 1);
+        icmp_grid.attach(&self.identifier_entry, 1, 2, 1, 1);
+
+        icmp_grid.attach(&gtk::Label::builder().label("Sequence:").halign(gtk::Align::Start).build(), 2, 2, 1, 1);
+        icmp_grid.attach(&self.sequence_entry, 3, 2, 1, 1);
 
         icmp_grid
     }
@@ -89,21 +139,60 @@ impl IcmpOptions {
             type_dropdown: gtk::DropDown::from_strings(&["Request", "Response"]),
             code_entry: gtk::Entry::builder().placeholder_text("ICMP code..").build(),
             checksum_entry: gtk::Entry::builder().placeholder_text("ICMP checksum..").build(),
+            identifier_entry: gtk::Entry::builder().placeholder_text("Echo identifier..").build(),
+            sequence_entry: gtk::Entry::builder().placeholder_text("Echo sequence..").build(),
             data_entry: gtk::Entry::builder().placeholder_text("Data..").build(),
+            escapes: gtk::CheckButton::with_label("Escapes"),
+
+            count_entry: gtk::Entry::builder().placeholder_text("4").build(),
+            interval_entry: gtk::Entry::builder().placeholder_text("1000").build(),
+            timeout_entry: gtk::Entry::builder().placeholder_text("1000").build()
+        }
+    }
+    fn payload(&self) -> Option<Vec<u8>> {
+        match self.data_entry.text().is_empty() {
+            true => Some("ICMP request".bytes().collect()),
+            false => entry_data(&self.data_entry, &self.escapes)
         }
     }
+    /* Only echo messages can be built here. The data is loaded escaped, so that binary data survives. */
+    fn load(&self, message: &[u8]) {
+        match message.first() {
+            Some(8) if message.len() >= 8 => self.type_dropdown.set_selected(0),
+            Some(0) if message.len() >= 8 => self.type_dropdown.set_selected(1),
+            _ => { error("Only ICMP echo messages can be loaded."); return; }
+        }
+        self.code_entry.set_text(&message[1].to_string());
+        self.checksum_entry.set_text("");
+        self.identifier_entry.set_text(&u16::from_be_bytes([message[4], message[5]]).to_string());
+        self.sequence_entry.set_text(&u16::from_be_bytes([message[6], message[7]]).to_string());
+        self.data_entry.set_text(&escape(&message[8..]));
+        self.escapes.set_active(true);
+    }
+    fn ping_parameters(&self) -> Option<PingParameters> {
+        let identifier = parse_entry(&self.identifier_entry, std::process::id() as u16, "ICMP identifier")?;
+        let first_sequence = parse_entry(&self.sequence_entry, 1u16, "ICMP sequence")?;
+        let count = parse_entry(&self.count_entry, 4u16, "ping count")?;
+        let interval = parse_entry(&self.interval_entry, 1000u64, "ping interval")?;
+        let timeout = parse_entry(&self.timeout_entry, 1000u64, "ping timeout")?;
+
+        Some(PingParameters {
+            identifier, first_sequence, count,
+            interval: Duration::from_millis(interval),
+            timeout: Duration::from_millis(timeout),
+            payload: self.payload()?
+        })
+    }
     fn build_packet(&self) -> Option<Vec<u8>> {
-        let packet_size = match self.data_entry.text().is_empty() {
-            true => MutableIcmpPacket::minimum_packet_size() + "ICMP request".to_string().bytes().len(),
-            false => MutableIcmpPacket::minimum_packet_size() + self.data_entry.text().bytes().len()
-        };
-        let mut packet = MutableIcmpPacket::owned(vec![0u8; packet_size]).unwrap();
-
-        let payload: Vec<_> = match self.data_entry.text().is_empty() {
-            true => "ICMP request".to_string().bytes().collect(),
-            false => self.data_entry.text().bytes().collect()
-        };
+        /* Identifier and sequence only go in front of the data when one of them is given. */
+        let mut payload = Vec::new();
+        if self.identifier_entry.text_length() > 0 || self.sequence_entry.text_length() > 0 {
+            payload.extend_from_slice(&parse_entry(&self.identifier_entry, 0u16, "ICMP identifier")?.to_be_bytes());
+            payload.extend_from_slice(&parse_entry(&self.sequence_entry, 0u16, "ICMP sequence")?.to_be_bytes());
+        }
+        payload.extend(self.payload()?);
 
+        let mut packet = MutableIcmpPacket::owned(vec![0u8; MutableIcmpPacket::minimum_packet_size() + payload.len()]).unwrap();
         packet.set_payload(&payload);
 
         match self.type_dropdown.selected() {
@@ -0,0 +1,336 @@
+use std::cell::RefCell;
+use std::net::Ipv6Addr;
+use std::rc::Rc;
+use std::str::FromStr;
+use gtk::prelude::*;
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::util::MacAddr;
+use crate::database::Database;
+
+use crate::error_window::error;
+use crate::widgets::MainWindowWidgets;
+use crate::widgets::parse_entry;
+use crate::widgets::parse_number;
+use crate::widgets::unescape;
+
+/* Message types in dropdown order. */
+const MESSAGE_TYPES: [(&str, u8); 9] = [("Echo request", 128), ("Echo reply", 129), ("Destination unreachable", 1),
+    ("Packet too big", 2), ("Router solicitation", 133), ("Router advertisement", 134), ("Neighbor solicitation", 135),
+    ("Neighbor advertisement", 136), ("Redirect", 137)];
+
+fn option(options: &mut Vec<u8>, kind: u8, data: &[u8]) {
+    let mut data = data.to_vec();
+    while (data.len() + 2) & 7 != 0 { data.push(0); }
+    options.push(kind);
+    options.push(((data.len() + 2) / 8) as u8);
+    options.append(&mut data);
+}
+
+fn parse_address(entry: &gtk::Entry, what: &str) -> Option<Ipv6Addr> {
+    match Ipv6Addr::from_str(&entry.text()) {
+        Ok(address) => Some(address),
+        Err(_) => { error(&("Bad ".to_owned() + what + " value")); None }
+    }
+}
+
+pub(crate) struct Icmpv6Options {
+    /* IPv6 header */
+    source: gtk::Entry,
+    destination: gtk::Entry,
+    hop_limit: gtk::Entry,
+    traffic_class: gtk::Entry,
+    flow_label: gtk::Entry,
+
+    message: gtk::DropDown,
+    code: gtk::Entry,
+    checksum: gtk::Entry,
+    identifier: gtk::Entry,
+    sequence: gtk::Entry,
+    mtu: gtk::Entry,
+    target: gtk::Entry,
+    redirect_destination: gtk::Entry,
+    data: gtk::Entry,
+
+    /* Router advertisement: current hop limit, managed, other, lifetime, reachable time, retransmission timer */
+    router: (gtk::Entry, gtk::CheckButton, gtk::CheckButton, gtk::Entry, gtk::Entry, gtk::Entry),
+    /* Neighbor advertisement: router, solicited, override */
+    neighbor: (gtk::CheckButton, gtk::CheckButton, gtk::CheckButton),
+
+    source_link: gtk::CheckButton,
+    target_link: gtk::CheckButton,
+    link_address: gtk::Entry,
+    prefix: gtk::Entry,
+    mtu_option: gtk::Entry
+}
+impl Icmpv6Options {
+    pub(crate) fn show_window(widgets: Rc<RefCell<MainWindowWidgets>>, database: Rc<RefCell<Database>>, send_now: bool) {
+        let icmp_widgets = Icmpv6Options::new();
+        let dialog = gtk::Dialog::with_buttons(
+            Some("ICMPv6 options"),
+            Some(&gtk::Window::new()),
+            gtk::DialogFlags::USE_HEADER_BAR,
+            &[("Ok", gtk::ResponseType::Ok), ("Cancel", gtk::ResponseType::Cancel)]);
+        dialog.content_area().append(&icmp_widgets.generate_ui());
+
+        dialog.connect_response(move |dialog, response| {
+            match response {
+                gtk::ResponseType::Ok => {
+                    let source_mac = match widgets.borrow().get_macs() {
+                        Some(macs) => macs.0,
+                        None => return
+                    };
+                    let packet = match icmp_widgets.build_packet(source_mac) {
+                        Some(value) => value,
+                        None => return
+                    };
+
+                    MainWindowWidgets::send_ipv6_packet(widgets.clone(), packet, database.clone(), "ICMPv6", send_now);
+                    dialog.close();
+                },
+                gtk::ResponseType::Cancel => {
+                    dialog.close();
+                },
+                _ => {}
+            }
+        });
+
+        dialog.show();
+    }
+
+    fn generate_ui(&self) -> gtk::Box {
+        let label = |text: &str| gtk::Label::builder().label(text).halign(gtk::Align::Start).build();
+
+        let ip_grid = gtk::Grid::builder().margin_start(12).margin_end(12).margin_top(12).margin_bottom(12).row_spacing(12)
+            .halign(gtk::Align::Center).valign(gtk::Align::Center).column_spacing(24).build();
+        ip_grid.attach(&label("Source:"), 0, 0, 1, 1);
+        ip_grid.attach(&self.source, 1, 0, 1, 1);
+        ip_grid.attach(&label("Destination:"), 2, 0, 1, 1);
+        ip_grid.attach(&self.destination, 3, 0, 1, 1);
+        ip_grid.attach(&self.hop_limit, 1, 1, 1, 1);
+        ip_grid.attach(&self.traffic_class, 2, 1, 1, 1);
+        ip_grid.attach(&self.flow_label, 3, 1, 1, 1);
+
+        let grid = gtk::Grid::builder().margin_start(24).margin_end(24).row_spacing(12)
+            .halign(gtk::Align::Center).valign(gtk::Align::Center).column_spacing(24).build();
+        grid.attach(&label("Type:"), 0, 0, 1, 1);
+        grid.attach(&self.message, 1, 0, 1, 1);
+        grid.attach(&label("Code:"), 2, 0, 1, 1);
+        grid.attach(&self.code, 3, 0, 1, 1);
+        grid.attach(&label("Checksum:"), 0, 1, 1, 1);
+        grid.attach(&self.checksum, 1, 1, 1, 1);
+        grid.attach(&label("Identifier / sequence:"), 2, 1, 1, 1);
+        let echo_box = gtk::Box::builder().orientation(gtk::Orientation::Horizontal).spacing(6).build();
+        echo_box.append(&self.identifier); echo_box.append(&self.sequence);
+        grid.attach(&echo_box, 3, 1, 1, 1);
+        grid.attach(&label("Target:"), 0, 2, 1, 1);
+        grid.attach(&self.target, 1, 2, 1, 1);
+        grid.attach(&label("Redirect destination:"), 2, 2, 1, 1);
+        grid.attach(&self.redirect_destination, 3, 2, 1, 1);
+        grid.attach(&label("MTU:"), 0, 3, 1, 1);
+        grid.attach(&self.mtu, 1, 3, 1, 1);
+        grid.attach(&label("Data / invoking packet:"), 2, 3, 1, 1);
+        grid.attach(&self.data, 3, 3, 1, 1);
+
+        let router_box = gtk::Box::builder().orientation(gtk::Orientation::Horizontal).spacing(12)
+            .margin_start(12).margin_end(12).margin_top(12).margin_bottom(12).build();
+        router_box.append(&self.router.0); router_box.append(&self.router.1); router_box.append(&self.router.2);
+        router_box.append(&self.router.3); router_box.append(&self.router.4); router_box.append(&self.router.5);
+
+        let neighbor_box = gtk::Box::builder().orientation(gtk::Orientation::Horizontal).spacing(12)
+            .margin_start(12).margin_end(12).margin_top(12).margin_bottom(12).build();
+        neighbor_box.append(&self.neighbor.0); neighbor_box.append(&self.neighbor.1); neighbor_box.append(&self.neighbor.2);
+
+        let options_grid = gtk::Grid::builder().margin_start(12).margin_end(12).margin_top(12).margin_bottom(12).row_spacing(12)
+            .halign(gtk::Align::Center).valign(gtk::Align::Center).column_spacing(24).build();
+        options_grid.attach(&self.source_link, 0, 0, 1, 1);
+        options_grid.attach(&self.target_link, 1, 0, 1, 1);
+        options_grid.attach(&self.link_address, 2, 0, 1, 1);
+        options_grid.attach(&self.prefix, 0, 1, 2, 1);
+        options_grid.attach(&self.mtu_option, 2, 1, 1, 1);
+
+        let main_box = gtk::Box::builder().orientation(gtk::Orientation::Vertical)
+            .halign(gtk::Align::Center).valign(gtk::Align::Center).spacing(24).margin_top(24).margin_bottom(24).build();
+        main_box.append(&gtk::Frame::builder().label("IPv6 header").child(&ip_grid).margin_start(24).margin_end(24).build());
+        main_box.append(&grid);
+        main_box.append(&gtk::Frame::builder().label("Router advertisement").child(&router_box).margin_start(24).margin_end(24).build());
+        main_box.append(&gtk::Frame::builder().label("Neighbor advertisement").child(&neighbor_box).margin_start(24).margin_end(24).build());
+        main_box.append(&gtk::Frame::builder().label("NDP options").child(&options_grid).margin_start(24).margin_end(24).build());
+
+        main_box
+    }
+
+    pub(crate) fn new() -> Icmpv6Options {
+        let names: Vec<&str> = MESSAGE_TYPES.iter().map(|(name, _)| *name).collect();
+        Icmpv6Options {
+            source: gtk::Entry::builder().placeholder_text("fe80::1").build(),
+            destination: gtk::Entry::builder().placeholder_text("ff02::1").build(),
+            hop_limit: gtk::Entry::builder().placeholder_text("Hop limit (255)").build(),
+            traffic_class: gtk::Entry::builder().placeholder_text("Traffic class (0)").build(),
+            flow_label: gtk::Entry::builder().placeholder_text("Flow label (0)").build(),
+
+            message: gtk::DropDown::from_strings(&names),
+            code: gtk::Entry::builder().placeholder_text("0").build(),
+            checksum: gtk::Entry::builder().placeholder_text("Auto").build(),
+            identifier: gtk::Entry::builder().placeholder_text("Identifier").build(),
+            sequence: gtk::Entry::builder().placeholder_text("Sequence").build(),
+            mtu: gtk::Entry::builder().placeholder_text("1280").build(),
+            target: gtk::Entry::builder().placeholder_text("Target address").build(),
+            redirect_destination: gtk::Entry::builder().placeholder_text("Destination address").build(),
+            data: gtk::Entry::builder().placeholder_text("Data, \\xNN escapes allowed").build(),
+
+            router: (gtk::Entry::builder().placeholder_text("Hop limit (64)").build(),
+                     gtk::CheckButton::with_label("Managed"), gtk::CheckButton::with_label("Other"),
+                     gtk::Entry::builder().placeholder_text("Lifetime, s (1800)").build(),
+                     gtk::Entry::builder().placeholder_text("Reachable, ms (0)").build(),
+                     gtk::Entry::builder().placeholder_text("Retrans, ms (0)").build()),
+            neighbor: (gtk::CheckButton::with_label("Router"), gtk::CheckButton::builder().label("Solicited").active(true).build(),
+                       gtk::CheckButton::builder().label("Override").active(true).build()),
+
+            source_link: gtk::CheckButton::with_label("Source link-layer address"),
+            target_link: gtk::CheckButton::with_label("Target link-layer address"),
+            link_address: gtk::Entry::builder().placeholder_text("Link-layer address (source MAC)").build(),
+            prefix: gtk::Entry::builder().placeholder_text("Prefix: 2001:db8::/64 valid=86400 preferred=14400 L A").build(),
+            mtu_option: gtk::Entry::builder().placeholder_text("MTU option").build()
+        }
+    }
+
+    /* The whole IPv6 packet, as there is no IPv6 section in the main window. */
+    fn build_packet(&self, source_mac: MacAddr) -> Option<Vec<u8>> {
+        let source = parse_address(&self.source, "IPv6 source address")?;
+        let destination = parse_address(&self.destination, "IPv6 destination address")?;
+        let hop_limit = parse_entry(&self.hop_limit, 255u8, "hop limit")?;
+        let traffic_class = parse_entry(&self.traffic_class, 0u8, "traffic class")?;
+        let flow_label = parse_entry(&self.flow_label, 0u32, "flow label")? & 0xfffff;
+
+        let message = self.build_message(source_mac, source, destination)?;
+
+        let mut packet = (6u32 << 28 | (traffic_class as u32) << 20 | flow_label).to_be_bytes().to_vec();
+        packet.extend_from_slice(&(message.len() as u16).to_be_bytes());
+        packet.push(58);
+        packet.push(hop_limit);
+        packet.extend_from_slice(&source.octets());
+        packet.extend_from_slice(&destination.octets());
+        packet.extend(message);
+        Some(packet)
+    }
+
+    fn build_message(&self, source_mac: MacAddr, source: Ipv6Addr, destination: Ipv6Addr) -> Option<Vec<u8>> {
+        let message_type = MESSAGE_TYPES[(self.message.selected() as usize).min(MESSAGE_TYPES.len() - 1)].1;
+        let code = parse_entry(&self.code, 0u8, "ICMPv6 code")?;
+        let data = match unescape(&self.data.text()) {
+            Ok(data) => data,
+            Err(what) => { error(&what); return None; }
+        };
+
+        let mut message = vec![message_type, code, 0, 0];
+        match message_type {
+            128 | 129 => {
+                message.extend_from_slice(&parse_entry(&self.identifier, std::process::id() as u16, "ICMPv6 identifier")?.to_be_bytes());
+                message.extend_from_slice(&parse_entry(&self.sequence, 1u16, "ICMPv6 sequence")?.to_be_bytes());
+                message.extend_from_slice(&data);
+            },
+            1 => {
+                message.extend_from_slice(&[0; 4]);
+                message.extend_from_slice(&data);
+            },
+            2 => {
+                message.extend_from_slice(&parse_entry(&self.mtu, 1280u32, "MTU")?.to_be_bytes());
+                message.extend_from_slice(&data);
+            },
+            133 => message.extend_from_slice(&[0; 4]),
+            134 => {
+                message.push(parse_entry(&self.router.0, 64u8, "current hop limit")?);
+                message.push((self.router.1.is_active() as u8) << 7 | (self.router.2.is_active() as u8) << 6);
+                message.extend_from_slice(&parse_entry(&self.router.3, 1800u16, "router lifetime")?.to_be_bytes());
+                message.extend_from_slice(&parse_entry(&self.router.4, 0u32, "reachable time")?.to_be_bytes());
+                message.extend_from_slice(&parse_entry(&self.router.5, 0u32, "retransmission timer")?.to_be_bytes());
+            },
+            135 => {
+                message.extend_from_slice(&[0; 4]);
+                message.extend_from_slice(&parse_address(&self.target, "target address")?.octets());
+            },
+            136 => {
+                message.push((self.neighbor.0.is_active() as u8) << 7 | (self.neighbor.1.is_active() as u8) << 6 | (self.neighbor.2.is_active() as u8) << 5);
+                message.extend_from_slice(&[0; 3]);
+                message.extend_from_slice(&parse_address(&self.target, "target address")?.octets());
+            },
+            _ => {
+                message.extend_from_slice(&[0; 4]);
+                message.extend_from_slice(&parse_address(&self.target, "target address")?.octets());
+                message.extend_from_slice(&parse_address(&self.redirect_destination, "redirect destination")?.octets());
+            }
+        }
+        if message_type >= 133 { message.extend(self.options(source_mac, message_type == 137, &data)?); }
+
+        let checksum = match self.checksum.text().is_empty() {
+            true => pnet::packet::util::ipv6_checksum(&message, 1, &[], &source, &destination, IpNextHeaderProtocols::Icmpv6),
+            false => match parse_number(&self.checksum.text()) {
+                Some(value) => value,
+                None => { error("Bad ICMPv6 checksum value"); return None; }
+            }
+        };
+        message[2..4].copy_from_slice(&checksum.to_be_bytes());
+        Some(message)
+    }
+
+    /* Redirects carry the data as the redirected header option. */
+    fn options(&self, source_mac: MacAddr, redirect: bool, data: &[u8]) -> Option<Vec<u8>> {
+        let link_address = match self.link_address.text().is_empty() {
+            true => source_mac,
+            false => match MacAddr::from_str(&self.link_address.text().replace('.', ":")) {
+                Ok(address) => address,
+                Err(_) => { error("Bad link-layer address value"); return None; }
+            }
+        };
+
+        let mut options = Vec::new();
+        if self.source_link.is_active() { option(&mut options, 1, &link_address.octets()); }
+        if self.target_link.is_active() { option(&mut options, 2, &link_address.octets()); }
+
+        if !self.prefix.text().is_empty() {
+            let text = self.prefix.text();
+            let mut fields = text.split_whitespace();
+            let prefix = fields.next().and_then(|v| v.split_once('/'))
+                .and_then(|(address, length)| Some((Ipv6Addr::from_str(address).ok()?, length.parse::<u8>().ok().filter(|v| *v <= 128)?)));
+            let (address, length) = match prefix {
+                Some(prefix) => prefix,
+                None => { error("Bad prefix information value"); return None; }
+            };
+            let (mut flags, mut valid, mut preferred) = (0u8, 86400u32, 14400u32);
+            for field in fields {
+                match field.split_once('=') {
+                    Some(("valid", value)) => valid = match value.parse() {
+                        Ok(value) => value,
+                        Err(_) => { error(&("Bad prefix valid lifetime ".to_owned() + value)); return None; }
+                    },
+                    Some(("preferred", value)) => preferred = match value.parse() {
+                        Ok(value) => value,
+                        Err(_) => { error(&("Bad prefix preferred lifetime ".to_owned() + value)); return None; }
+                    },
+                    _ if field == "L" => flags |= 0x80,
+                    _ if field == "A" => flags |= 0x40,
+                    _ => { error(&("Bad prefix information field ".to_owned() + field)); return None; }
+                }
+            }
+            let mut data = vec![length, flags];
+            data.extend_from_slice(&valid.to_be_bytes());
+            data.extend_from_slice(&preferred.to_be_bytes());
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(&address.octets());
+            option(&mut options, 3, &data);
+        }
+
+        if redirect && !data.is_empty() {
+            let mut header = vec![0; 6];
+            header.extend_from_slice(data);
+            option(&mut options, 4, &header);
+        }
+
+        if !self.mtu_option.text().is_empty() {
+            let mut data = vec![0, 0];
+            data.extend_from_slice(&parse_entry(&self.mtu_option, 1500u32, "MTU option")?.to_be_bytes());
+            option(&mut options, 5, &data);
+        }
+        Some(options)
+    }
+}
@@ -0,0 +1,217 @@
+use std::cell::RefCell;
+use std::net::Ipv4Addr;
+use std::rc::Rc;
+use std::str::FromStr;
+use gtk::prelude::*;
+use pnet::packet::ip::IpNextHeaderProtocol;
+use crate::database::Database;
+
+use crate::error_window::error;
+use crate::widgets::MainWindowWidgets;
+use crate::widgets::parse_entry;
+use crate::widgets::parse_number;
+
+/* IGMPv3 group record types in RFC 3376 order, starting from 1. */
+const RECORD_TYPES: [&str; 6] = ["is_in", "is_ex", "to_in", "to_ex", "allow", "block"];
+
+fn parse_addresses(text: &str) -> Option<Vec<Ipv4Addr>> {
+    text.split([',', ' ']).filter(|v| !v.is_empty()).map(|v| Ipv4Addr::from_str(v).ok()).collect()
+}
+
+/* Exponential encoding of RFC 3376 for the max response code and QQIC, exact below 128. */
+fn encode_time(value: u32) -> u8 {
+    if value < 128 { return value as u8; }
+    for exponent in 0..8u32 {
+        let mantissa = (value >> (exponent + 3)).saturating_sub(16);
+        if mantissa < 16 { return 0x80 | (exponent as u8) << 4 | mantissa as u8; }
+    }
+    0xff
+}
+
+pub(crate) struct IgmpOptions {
+    version: gtk::DropDown,
+    message: gtk::DropDown,
+    group: gtk::Entry,
+    max_response: gtk::Entry,
+    checksum: gtk::Entry,
+
+    /* IGMPv3 query */
+    suppress: gtk::CheckButton,
+    robustness: gtk::Entry,
+    interval: gtk::Entry,
+    sources: gtk::Entry,
+
+    /* IGMPv3 report */
+    records: gtk::TextView
+}
+impl IgmpOptions {
+    pub(crate) fn show_window(widgets: Rc<RefCell<MainWindowWidgets>>, database: Rc<RefCell<Database>>, send_now: bool) {
+        let igmp_widgets = IgmpOptions::new();
+        let dialog = gtk::Dialog::with_buttons(
+            Some("IGMP options"),
+            Some(&gtk::Window::new()),
+            gtk::DialogFlags::USE_HEADER_BAR,
+            &[("Ok", gtk::ResponseType::Ok), ("Cancel", gtk::ResponseType::Cancel)]);
+        dialog.content_area().append(&igmp_widgets.generate_ui());
+
+        dialog.connect_response(move |dialog, response| {
+            match response {
+                gtk::ResponseType::Ok => {
+                    let igmp_packet = match igmp_widgets.build_packet() {
+                        Some(value) => value,
+                        None => return
+                    };
+
+                    MainWindowWidgets::build_ip_packet(widgets.clone(), igmp_packet, IpNextHeaderProtocol::new(2), database.clone(), "IGMP", send_now);
+                    dialog.close();
+                },
+                gtk::ResponseType::Cancel => {
+                    dialog.close();
+                },
+                _ => {}
+            }
+        });
+
+        dialog.show();
+    }
+
+    fn generate_ui(&self) -> gtk::Box {
+        let grid = gtk::Grid::builder().margin_start(24).margin_end(24).margin_top(24).row_spacing(24)
+            .halign(gtk::Align::Center).valign(gtk::Align::Center).column_spacing(24).build();
+
+        grid.attach(&gtk::Label::builder().label("Version:").halign(gtk::Align::Start).build(), 0, 0, 1, 1);
+        grid.attach(&self.version, 1, 0, 1, 1);
+        grid.attach(&gtk::Label::builder().label("Message:").halign(gtk::Align::Start).build(), 2, 0, 1, 1);
+        grid.attach(&self.message, 3, 0, 1, 1);
+
+        grid.attach(&gtk::Label::builder().label("Group:").halign(gtk::Align::Start).build(), 0, 1, 1, 1);
+        grid.attach(&self.group, 1, 1, 1, 1);
+        grid.attach(&gtk::Label::builder().label("Max response (0.1 s):").halign(gtk::Align::Start).build(), 2, 1, 1, 1);
+        grid.attach(&self.max_response, 3, 1, 1, 1);
+
+        grid.attach(&gtk::Label::builder().label("Checksum:").halign(gtk::Align::Start).build(), 0, 2, 1, 1);
+        grid.attach(&self.checksum, 1, 2, 1, 1);
+
+        let query_grid = gtk::Grid::builder().margin_start(12).margin_end(12).margin_top(12).margin_bottom(12).row_spacing(12)
+            .halign(gtk::Align::Center).valign(gtk::Align::Center).column_spacing(24).build();
+        query_grid.attach(&self.suppress, 0, 0, 1, 1);
+        query_grid.attach(&self.robustness, 1, 0, 1, 1);
+        query_grid.attach(&self.interval, 2, 0, 1, 1);
+        query_grid.attach(&self.sources, 0, 1, 3, 1);
+
+        let hint = "One group record per line: TYPE GROUP [SOURCE ...], type is is_in, is_ex, to_in, to_ex, allow or block.";
+        let report_box = gtk::Box::builder().orientation(gtk::Orientation::Vertical).spacing(6)
+            .margin_start(12).margin_end(12).margin_top(12).margin_bottom(12).build();
+        report_box.append(&gtk::Label::builder().label(hint).halign(gtk::Align::Start).build());
+        report_box.append(&gtk::ScrolledWindow::builder().child(&self.records).min_content_height(80).build());
+
+        let main_box = gtk::Box::builder().orientation(gtk::Orientation::Vertical)
+            .halign(gtk::Align::Center).valign(gtk::Align::Center).spacing(24).margin_bottom(24).build();
+        main_box.append(&grid);
+        main_box.append(&gtk::Frame::builder().label("IGMPv3 query").child(&query_grid).margin_start(24).margin_end(24).build());
+        main_box.append(&gtk::Frame::builder().label("IGMPv3 report").child(&report_box).margin_start(24).margin_end(24).build());
+
+        main_box
+    }
+
+    pub(crate) fn new() -> IgmpOptions {
+        IgmpOptions {
+            version: gtk::DropDown::from_strings(&["IGMPv1", "IGMPv2", "IGMPv3"]),
+            message: gtk::DropDown::from_strings(&["Membership query", "Membership report", "Leave group"]),
+            group: gtk::Entry::builder().placeholder_text("0.0.0.0").build(),
+            max_response: gtk::Entry::builder().placeholder_text("100").build(),
+            checksum: gtk::Entry::builder().placeholder_text("Auto").build(),
+
+            suppress: gtk::CheckButton::with_label("Suppress router processing"),
+            robustness: gtk::Entry::builder().placeholder_text("Robustness (2)").build(),
+            interval: gtk::Entry::builder().placeholder_text("Query interval, s (125)").build(),
+            sources: gtk::Entry::builder().placeholder_text("Sources: 10.0.0.1, 10.0.0.2").build(),
+
+            records: gtk::TextView::builder().monospace(true).build()
+        }
+    }
+
+    fn build_packet(&self) -> Option<Vec<u8>> {
+        let version = self.version.selected() + 1;
+        let group = parse_entry(&self.group, Ipv4Addr::UNSPECIFIED, "IGMP group")?;
+        let max_response = parse_entry(&self.max_response, 100u32, "IGMP max response time")?;
+
+        let mut packet = match (version, self.message.selected()) {
+            (1, 0) => vec![0x11, 0],
+            (1, 1) => vec![0x12, 0],
+            (1, _) => { error("IGMPv1 has no leave message"); return None; },
+            (2, 0) => vec![0x11, max_response.min(255) as u8],
+            (2, 1) => vec![0x16, 0],
+            (2, _) => vec![0x17, 0],
+            (_, 0) => vec![0x11, encode_time(max_response)],
+            (_, message) => return self.v3_report(message == 2, group)
+        };
+        packet.extend_from_slice(&[0, 0]);
+        packet.extend_from_slice(&group.octets());
+
+        if version == 3 {
+            let robustness = parse_entry(&self.robustness, 2u8, "IGMP robustness")?;
+            let interval = parse_entry(&self.interval, 125u32, "IGMP query interval")?;
+            let sources = match parse_addresses(&self.sources.text()) {
+                Some(sources) => sources,
+                None => { error("Bad IGMP source list"); return None; }
+            };
+            packet.push((self.suppress.is_active() as u8) << 3 | robustness.min(7));
+            packet.push(encode_time(interval));
+            packet.extend_from_slice(&(sources.len() as u16).to_be_bytes());
+            for source in sources { packet.extend_from_slice(&source.octets()); }
+        }
+
+        self.set_checksum(packet)
+    }
+
+    /* A leave is a change to include mode with no sources for the group. */
+    fn v3_report(&self, leave: bool, group: Ipv4Addr) -> Option<Vec<u8>> {
+        let mut records = Vec::new();
+        let mut count = 0u16;
+        let mut append = |record_type: u8, group: Ipv4Addr, sources: &[Ipv4Addr]| {
+            records.extend_from_slice(&[record_type, 0]);
+            records.extend_from_slice(&(sources.len() as u16).to_be_bytes());
+            records.extend_from_slice(&group.octets());
+            for source in sources { records.extend_from_slice(&source.octets()); }
+            count += 1;
+        };
+
+        if leave {
+            append(3, group, &[]);
+        } else {
+            let buffer = self.records.buffer();
+            let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+            for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+                let mut fields = line.split_whitespace();
+                let record_type = fields.next().and_then(|name| match RECORD_TYPES.iter().position(|known| known.eq_ignore_ascii_case(name)) {
+                    Some(index) => Some(index as u8 + 1),
+                    None => parse_number::<u8>(name)
+                });
+                let group = fields.next().and_then(|v| Ipv4Addr::from_str(v).ok());
+                let sources = parse_addresses(&fields.collect::<Vec<_>>().join(" "));
+                match (record_type, group, sources) {
+                    (Some(record_type), Some(group), Some(sources)) => append(record_type, group, &sources),
+                    _ => { error(&("Bad IGMP group record ".to_owned() + line)); return None; }
+                }
+            }
+        }
+
+        let mut packet = vec![0x22, 0, 0, 0, 0, 0];
+        packet.extend_from_slice(&count.to_be_bytes());
+        packet.append(&mut records);
+        self.set_checksum(packet)
+    }
+
+    fn set_checksum(&self, mut packet: Vec<u8>) -> Option<Vec<u8>> {
+        let checksum = match self.checksum.text().is_empty() {
+            true => pnet::util::checksum(&packet, 1),
+            false => match parse_number(&self.checksum.text()) {
+                Some(value) => value,
+                None => { error("Bad IGMP checksum value"); return None; }
+            }
+        };
+        packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+        Some(packet)
+    }
+}
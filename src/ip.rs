@@ -13,7 +13,7 @@ use pnet::packet::ipv4::MutableIpv4Packet;
 use std::str::FromStr;
 
 use crate::error_window::error;
-use crate::show_packet::show;
+use crate::widgets::set_auto_field;
 
 pub(crate) struct IPWidgets {
     src_ip: gtk::Entry,
@@ -339,7 +339,54 @@ impl IPWidgets {
 
         Some((src, dest))
     }
+    pub(crate) fn set_addresses(&self, source: Ipv4Addr, destination: Ipv4Addr) {
+        self.src_ip.set_text(&source.to_string());
+        self.dest_ip.set_text(&destination.to_string());
+    }
+    /* Header fields of a stored packet, returns its payload. Options without a name here are dropped. */
+    pub(crate) fn load<'a>(&self, packet: &'a [u8]) -> &'a [u8] {
+        let header_length = ((packet[0] & 0x0f) as usize * 4).clamp(20, packet.len());
+        let value = |value: u32, default: u32| if value == default { None } else { Some(value.to_string()) };
+        let fragment = u16::from_be_bytes([packet[6], packet[7]]);
+
+        self.set_addresses(Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]),
+                           Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]));
+        set_auto_field(&self.version, value((packet[0] >> 4) as u32, 4));
+        set_auto_field(&self.header_length, value((packet[0] & 0x0f) as u32, 5));
+        set_auto_field(&self.dscp, value((packet[1] >> 2) as u32, 0));
+        set_auto_field(&self.ecn, value((packet[1] & 3) as u32, 0));
+        set_auto_field(&self.packet_length, None);
+        set_auto_field(&self.packet_id, value(u16::from_be_bytes([packet[4], packet[5]]) as u32, 12345));
+        set_auto_field(&self.offset, value((fragment & 0x1fff) as u32, 0));
+        set_auto_field(&self.ttl, value(packet[8] as u32, 64));
+        set_auto_field(&self.checksum, None);
+        self.flags.0.set_active(fragment & 0x4000 != 0);
+        self.flags.1.set_active(fragment & 0x2000 != 0);
+        self.flags.2.set_active(fragment & 0x8000 != 0);
+
+        let names = ["EOL", "NOP", "SEC", "LSR", "TS", "ESEC", "CIPSO", "RR", "SID", "SSR", "ZSU", "MTUP", "MTUR", "FINN",
+            "VISA", "ENCODE", "IMITD", "EIP", "TR", "ADDEXT", "RTRALT", "SDB", "", "DPS", "UMP", "QS", "", "", "", "", "EXP"];
+        let mut options = Vec::new();
+        let mut offset = 20;
+        while offset < header_length {
+            let number = (packet[offset] & 0x1f) as usize;
+            if let Some(name) = names.get(number).filter(|name| !name.is_empty()) { options.push(*name); }
+            offset += match number {
+                0 | 1 => 1,
+                _ => packet.get(offset + 1).map(|v| (*v as usize).max(2)).unwrap_or(header_length)
+            };
+        }
+        self.options.set_text(&options.join(", "));
+
+        let end = (u16::from_be_bytes([packet[2], packet[3]]) as usize).clamp(header_length, packet.len());
+        &packet[header_length..end]
+    }
     pub(crate) fn build_packet(&self, next_protocol: IpNextHeaderProtocol, data: &[u8]) -> Option<Vec<u8>> {
+        let addresses = self.get_addresses()?;
+        self.build_packet_between(addresses, next_protocol, data)
+    }
+    /* Same header fields with other addresses, used for outer tunnel headers. */
+    pub(crate) fn build_packet_between(&self, addresses: (Ipv4Addr, Ipv4Addr), next_protocol: IpNextHeaderProtocol, data: &[u8]) -> Option<Vec<u8>> {
         let options = match self.get_options() {
             Some(options) => options,
             None => Vec::new()
@@ -361,13 +408,8 @@ impl IPWidgets {
 
         packet.set_header_length(header_length);
 
-        match self.get_addresses() {
-            Some(addresses) => {
-                packet.set_source(addresses.0);
-                packet.set_destination(addresses.1);
-            },
-            None => return None
-        }
+        packet.set_source(addresses.0);
+        packet.set_destination(addresses.1);
 
         if self.version.0.is_active() {
             packet.set_version(4);
@@ -451,8 +493,6 @@ impl IPWidgets {
             }
         }
 
-        let payload = Vec::from(packet.packet());
-        show("IPv4 packet", &payload);
-        Some(payload)
+        Some(Vec::from(packet.packet()))
     }
 }
\ No newline at end of file
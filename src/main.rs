@@ -11,6 +11,28 @@ mod udp;
 mod ip;
 mod tcp;
 mod show_packet;
+mod channel;
+mod template;
+mod ping;
+mod report_window;
+mod traceroute;
+mod dissect;
+mod capture;
+mod session;
+mod fragment;
+mod segment;
+mod ethernet;
+mod tunnel;
+mod dns;
+mod dhcp;
+mod templates;
+mod igmp;
+mod icmpv6;
+mod sctp;
+mod sequence;
+mod transmit;
+mod pcap;
+mod replay;
 
 fn main() {
     let application = gtk::Application::builder()
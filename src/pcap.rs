@@ -0,0 +1,152 @@
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use pnet::datalink::DataLinkSender;
+use pnet::datalink::NetworkInterface;
+
+/* Classic libpcap files in either byte order, with microsecond or nanosecond timestamps. Only Ethernet
+ * captures are accepted, pcapng is not. Written files are little endian with microsecond timestamps. */
+
+const LINKTYPE_ETHERNET: u32 = 1;
+
+pub(crate) struct Record {
+    pub(crate) time: Duration,
+    pub(crate) data: Vec<u8>
+}
+
+pub(crate) fn read(path: &str) -> Result<Vec<Record>, String> {
+    let file = fs::read(path).map_err(|what| format!("Failed to read {}: {}", path, what))?;
+    if file.len() < 24 { return Err(format!("{} is too short for a pcap file.", path)); }
+
+    let magic = [file[0], file[1], file[2], file[3]];
+    let (little_endian, nanoseconds) = match magic {
+        [0xd4, 0xc3, 0xb2, 0xa1] => (true, false),
+        [0xa1, 0xb2, 0xc3, 0xd4] => (false, false),
+        [0x4d, 0x3c, 0xb2, 0xa1] => (true, true),
+        [0xa1, 0xb2, 0x3c, 0x4d] => (false, true),
+        [0x0a, 0x0d, 0x0d, 0x0a] => return Err("pcapng files are not supported, save the capture as pcap.".to_string()),
+        _ => return Err(format!("{} is not a pcap file.", path))
+    };
+    let word = |offset: usize| {
+        let bytes = [file[offset], file[offset + 1], file[offset + 2], file[offset + 3]];
+        match little_endian {
+            true => u32::from_le_bytes(bytes),
+            false => u32::from_be_bytes(bytes)
+        }
+    };
+
+    let linktype = word(20) & 0x0fff_ffff;
+    if linktype != LINKTYPE_ETHERNET { return Err(format!("Link type {} is not supported, only Ethernet captures are.", linktype)); }
+
+    let mut records = Vec::new();
+    let mut offset = 24;
+    while offset + 16 <= file.len() {
+        let fraction = word(offset + 4) as u64;
+        let time = Duration::from_secs(word(offset) as u64) + match nanoseconds {
+            true => Duration::from_nanos(fraction),
+            false => Duration::from_micros(fraction)
+        };
+        let length = word(offset + 8) as usize;
+        let data = match file.get(offset + 16..offset + 16 + length) {
+            Some(data) => data.to_vec(),
+            None => return Err(format!("Packet {} of {} is cut short.", records.len(), path))
+        };
+        records.push(Record { time, data });
+        offset += 16 + length;
+    }
+    Ok(records)
+}
+
+/* Sender of the "pcap:PATH" pseudo-interface. Frames are appended, so one file can record several runs, and
+ * every frame is written through at once so that the file is complete whenever sending stops. A file that is
+ * not empty must have been written the same way: little endian, microseconds and Ethernet. */
+pub(crate) struct PcapSender {
+    file: fs::File
+}
+impl PcapSender {
+    pub(crate) fn open(path: &str) -> Result<PcapSender, String> {
+        let mut file = fs::OpenOptions::new().create(true).read(true).append(true).open(path)
+            .map_err(|what| format!("Failed to open {}: {}", path, what))?;
+        let length = file.metadata().map_err(|what| format!("Failed to open {}: {}", path, what))?.len();
+        if length > 0 {
+            let mut header = [0; 24];
+            file.read_exact(&mut header).map_err(|_| format!("{} is not a pcap file, not appending to it.", path))?;
+            let linktype = u32::from_le_bytes([header[20], header[21], header[22], header[23]]);
+            if header[..4] != 0xa1b2c3d4u32.to_le_bytes() || linktype != LINKTYPE_ETHERNET {
+                return Err(format!("{} is not a little endian, microsecond Ethernet pcap file, not appending to it.", path));
+            }
+        } else {
+            let mut header = Vec::with_capacity(24);
+            header.extend_from_slice(&0xa1b2c3d4u32.to_le_bytes());
+            header.extend_from_slice(&2u16.to_le_bytes());
+            header.extend_from_slice(&4u16.to_le_bytes());
+            header.extend_from_slice(&[0; 8]);
+            header.extend_from_slice(&262144u32.to_le_bytes());
+            header.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+            file.write_all(&header).map_err(|what| format!("Failed to write {}: {}", path, what))?;
+        }
+        Ok(PcapSender { file })
+    }
+}
+impl DataLinkSender for PcapSender {
+    fn build_and_send(&mut self, num_packets: usize, packet_size: usize, func: &mut dyn FnMut(&mut [u8])) -> Option<io::Result<()>> {
+        for _ in 0..num_packets {
+            let mut packet = vec![0; packet_size];
+            func(&mut packet);
+            if let Some(Err(what)) = self.send_to(&packet, None) { return Some(Err(what)); }
+        }
+        Some(Ok(()))
+    }
+
+    fn send_to(&mut self, packet: &[u8], _dst: Option<NetworkInterface>) -> Option<io::Result<()>> {
+        let time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+        let mut record = Vec::with_capacity(16 + packet.len());
+        record.extend_from_slice(&(time.as_secs() as u32).to_le_bytes());
+        record.extend_from_slice(&time.subsec_micros().to_le_bytes());
+        record.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        record.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        record.extend_from_slice(packet);
+        Some(self.file.write_all(&record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("{}-{}.pcap", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn written_frames_read_back() {
+        let path = temporary("round-trip");
+        let frames = [vec![1u8; 60], vec![2u8; 1514], Vec::new()];
+        /* Every frame reopens the file, so appending must not write a second header. */
+        for frame in &frames {
+            let mut sender = PcapSender::open(&path).unwrap();
+            assert!(matches!(sender.send_to(frame, None), Some(Ok(()))));
+        }
+        let records = read(&path);
+        fs::remove_file(&path).unwrap();
+        let data: Vec<Vec<u8>> = records.unwrap().into_iter().map(|record| record.data).collect();
+        assert_eq!(data, frames);
+    }
+
+    #[test]
+    fn other_files_are_not_appended_to() {
+        let path = temporary("foreign");
+        fs::write(&path, b"This is not a capture file, just some text.").unwrap();
+        let opened = PcapSender::open(&path).is_ok();
+        let contents = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(!opened);
+        assert_eq!(contents, b"This is not a capture file, just some text.");
+    }
+}
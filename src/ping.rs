@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::Ipv4Addr;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use gtk::glib;
+use pnet::packet::Packet;
+use pnet::packet::icmp::IcmpCode;
+use pnet::packet::icmp::IcmpPacket;
+use pnet::packet::icmp::IcmpTypes;
+use pnet::packet::icmp::echo_reply::EchoReplyPacket;
+use pnet::packet::icmp::echo_request::MutableEchoRequestPacket;
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::Ipv4Packet;
+
+use crate::dissect;
+use crate::channel::Channel;
+use crate::report_window::ReportWindow;
+use crate::template::FrameTemplate;
+
+pub(crate) struct PingParameters {
+    pub(crate) identifier: u16,
+    pub(crate) first_sequence: u16,
+    pub(crate) count: u16,
+    pub(crate) interval: Duration,
+    pub(crate) timeout: Duration,
+    pub(crate) payload: Vec<u8>
+}
+
+enum PingEvent {
+    Reply { sequence: u16, size: usize, ttl: u8, rtt: Duration },
+    Lost(u16),
+    Failed(String),
+    Finished(PingStatistics)
+}
+
+struct PingStatistics {
+    transmitted: usize,
+    rtts: Vec<Duration>
+}
+impl PingStatistics {
+    fn summary(&self, destination: Ipv4Addr) -> String {
+        let received = self.rtts.len();
+        let loss = match self.transmitted {
+            0 => 0.0,
+            transmitted => 100.0 * (transmitted - received) as f64 / transmitted as f64
+        };
+
+        let mut result = format!("--- {} ping statistics ---\n{} packets transmitted, {} received, {:.0}% packet loss",
+                                 destination, self.transmitted, received, loss);
+        if received > 0 {
+            let values: Vec<f64> = self.rtts.iter().map(|rtt| rtt.as_secs_f64() * 1000.0).collect();
+            let min = values.iter().cloned().fold(f64::MAX, f64::min);
+            let max = values.iter().cloned().fold(0.0, f64::max);
+            let avg = values.iter().sum::<f64>() / received as f64;
+            let mdev = (values.iter().map(|v| v * v).sum::<f64>() / received as f64 - avg * avg).max(0.0).sqrt();
+            result += &format!("\nrtt min/avg/max/mdev = {:.3}/{:.3}/{:.3}/{:.3} ms", min, avg, max, mdev);
+        }
+        result
+    }
+}
+
+pub(crate) fn start(template: FrameTemplate, channel: Channel, parameters: PingParameters) {
+    let destination = template.destination();
+
+    let report = ReportWindow::new(&format!("Ping {}", destination));
+    report.append(&format!("PING {} {} data bytes", destination, parameters.payload.len()));
+
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    thread::spawn(move || run(template, channel, parameters, sender));
+
+    report.show();
+    receiver.attach(None, move |event| {
+        let text = match event {
+            PingEvent::Reply { sequence, size, ttl, rtt } =>
+                format!("{} bytes from {}: icmp_seq={} ttl={} time={:.3} ms", size, destination, sequence, ttl, rtt.as_secs_f64() * 1000.0),
+            PingEvent::Lost(sequence) => format!("Request timeout for icmp_seq {}", sequence),
+            PingEvent::Failed(what) => what,
+            PingEvent::Finished(statistics) => statistics.summary(destination)
+        };
+        report.append(&text);
+        glib::Continue(true)
+    });
+}
+
+fn run(template: FrameTemplate, channel: Channel, parameters: PingParameters, sender: glib::Sender<PingEvent>) {
+    let (mut tx, mut rx) = channel;
+    let destination = template.destination();
+
+    let mut statistics = PingStatistics { transmitted: 0, rtts: Vec::new() };
+    let mut pending: HashMap<u16, Instant> = HashMap::new();
+
+    for i in 0..parameters.count {
+        let sequence = parameters.first_sequence.wrapping_add(i);
+        let frame = template.build(&echo_request(parameters.identifier, sequence, &parameters.payload));
+        match tx.send_to(&frame, None) {
+            Some(Ok(_)) => {},
+            _ => { let _ = sender.send(PingEvent::Failed("Failed to send packet ".to_owned() + &sequence.to_string())); return; }
+        }
+        pending.insert(sequence, Instant::now());
+        statistics.transmitted += 1;
+
+        let wait = match i + 1 == parameters.count {
+            true => parameters.timeout,
+            false => parameters.interval
+        };
+        let deadline = Instant::now() + wait;
+        while Instant::now() < deadline && !(i + 1 == parameters.count && pending.is_empty()) {
+            match rx.next() {
+                Ok(frame) => {
+                    if let Some((sequence, size, ttl)) = match_reply(frame, destination, parameters.identifier) {
+                        if let Some(sent) = pending.remove(&sequence) {
+                            let rtt = sent.elapsed();
+                            statistics.rtts.push(rtt);
+                            let _ = sender.send(PingEvent::Reply { sequence, size, ttl, rtt });
+                        }
+                    }
+                },
+                Err(what) if matches!(what.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock | ErrorKind::Interrupted) => {},
+                Err(what) => { let _ = sender.send(PingEvent::Failed("Failed to receive packet: ".to_owned() + &what.to_string())); return; }
+            }
+
+            let mut expired: Vec<u16> = pending.iter()
+                .filter(|(_, sent)| sent.elapsed() >= parameters.timeout).map(|(sequence, _)| *sequence).collect();
+            expired.sort_unstable();
+            for sequence in expired {
+                pending.remove(&sequence);
+                let _ = sender.send(PingEvent::Lost(sequence));
+            }
+        }
+    }
+
+    let mut lost: Vec<u16> = pending.into_keys().collect();
+    lost.sort_unstable();
+    for sequence in lost { let _ = sender.send(PingEvent::Lost(sequence)); }
+
+    let _ = sender.send(PingEvent::Finished(statistics));
+}
+
+pub(crate) fn echo_request(identifier: u16, sequence: u16, payload: &[u8]) -> Vec<u8> {
+    let mut packet = MutableEchoRequestPacket::owned(vec![0u8; MutableEchoRequestPacket::minimum_packet_size() + payload.len()]).unwrap();
+    packet.set_icmp_type(IcmpTypes::EchoRequest);
+    packet.set_icmp_code(IcmpCode::new(0));
+    packet.set_identifier(identifier);
+    packet.set_sequence_number(sequence);
+    packet.set_payload(payload);
+
+    let checksum = pnet::packet::icmp::checksum(&IcmpPacket::new(packet.packet()).unwrap());
+    packet.set_checksum(checksum);
+    Vec::from(packet.packet())
+}
+
+fn match_reply(frame: &[u8], destination: Ipv4Addr, identifier: u16) -> Option<(u16, usize, u8)> {
+
+    let ip = Ipv4Packet::new(dissect::ipv4_payload(frame)?)?;
+    if ip.get_source() != destination || ip.get_next_level_protocol() != IpNextHeaderProtocols::Icmp { return None; }
+
+    let reply = EchoReplyPacket::new(ip.payload())?;
+    if reply.get_icmp_type() != IcmpTypes::EchoReply || reply.get_identifier() != identifier { return None; }
+
+    Some((reply.get_sequence_number(), ip.payload().len(), ip.get_ttl()))
+}
@@ -0,0 +1,290 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::Duration;
+
+use gtk::prelude::*;
+use pnet::util::MacAddr;
+
+use crate::database::Database;
+use crate::dissect;
+use crate::error_window::error;
+use crate::pcap;
+use crate::sequence;
+use crate::sequence::format_delay;
+use crate::transmit::Transmission;
+use crate::widgets::MainWindowWidgets;
+use crate::widgets::parse_entry;
+
+/* Address and port rewriting of replayed frames. Networks map host bits onto the new prefix, the first
+ * matching rule wins. Only IPv4 addresses, and TCP and UDP ports of first fragments, are rewritten. */
+struct Rewrite {
+    source: Option<MacAddr>,
+    destination: Option<MacAddr>,
+    networks: Vec<(u32, u32, u32)>,
+    ports: HashMap<u16, u16>,
+    checksums: bool
+}
+impl Rewrite {
+    fn apply(&self, frame: &mut [u8]) {
+        if frame.len() < 14 { return; }
+        if let Some(source) = self.source { frame[6..12].copy_from_slice(&source.octets()); }
+        if let Some(destination) = self.destination { frame[0..6].copy_from_slice(&destination.octets()); }
+
+        let offset = match dissect::ipv4_payload(frame) {
+            Some(packet) if packet.len() >= 20 => frame.len() - packet.len(),
+            _ => {
+                if self.checksums { dissect::fix_checksums(frame); }
+                return;
+            }
+        };
+        let packet = &mut frame[offset..];
+        let header_length = (packet[0] & 0x0f) as usize * 4;
+        let original = packet[..packet.len().min(header_length + 4)].to_vec();
+        for position in [12, 16] {
+            let address = u32::from_be_bytes([packet[position], packet[position + 1], packet[position + 2], packet[position + 3]]);
+            if let Some((_, mask, new)) = self.networks.iter().find(|(network, mask, _)| address & mask == *network) {
+                packet[position..position + 4].copy_from_slice(&(new | address & !mask).to_be_bytes());
+            }
+        }
+
+        let fragment = u16::from_be_bytes([packet[6], packet[7]]);
+        if matches!(packet[9], 6 | 17) && fragment & 0x1fff == 0 && packet.len() >= header_length + 4 {
+            for position in [header_length, header_length + 2] {
+                let port = u16::from_be_bytes([packet[position], packet[position + 1]]);
+                if let Some(new) = self.ports.get(&port) { packet[position..position + 2].copy_from_slice(&new.to_be_bytes()); }
+            }
+            /* The transport checksum of a first fragment covers the other fragments too, so it is only
+             * adjusted for the rewritten words. */
+            if self.checksums && fragment & 0x2000 != 0 { adjust_checksum(packet, &original, header_length); }
+        }
+
+        if self.checksums { dissect::fix_checksums(frame); }
+    }
+}
+
+/* Incremental update of RFC 1624 for the changed address and port words. A UDP checksum of 0 stays unset. */
+fn adjust_checksum(packet: &mut [u8], original: &[u8], header_length: usize) {
+    let position = header_length + match packet[9] {
+        6 => 16,
+        _ => 6
+    };
+    let stored = match packet.get(position..position + 2) {
+        Some(stored) => u16::from_be_bytes([stored[0], stored[1]]),
+        None => return
+    };
+    if packet[9] == 17 && stored == 0 { return; }
+
+    let word = |data: &[u8], offset: usize| u16::from_be_bytes([data[offset], data[offset + 1]]) as u32;
+    let mut sum = !stored as u32;
+    for offset in [12, 14, 16, 18, header_length, header_length + 2] {
+        sum += (!word(original, offset) & 0xffff) + word(packet, offset);
+    }
+    while sum > 0xffff { sum = (sum & 0xffff) + (sum >> 16); }
+    let checksum = match !(sum as u16) {
+        0 if packet[9] == 17 => 0xffff,
+        checksum => checksum
+    };
+    packet[position..position + 2].copy_from_slice(&checksum.to_be_bytes());
+}
+
+/* "10.0.0.0/8=192.168.0.0, 1.2.3.4=5.6.7.8", a missing prefix length is a single host. */
+fn parse_networks(text: &str) -> Option<Vec<(u32, u32, u32)>> {
+    let mut result = Vec::new();
+    for rule in text.split(',').map(str::trim).filter(|rule| !rule.is_empty()) {
+        let parsed = rule.split_once('=').and_then(|(from, to)| {
+            let (network, length) = match from.trim().split_once('/') {
+                Some((network, length)) => (network, length.trim().parse::<u32>().ok().filter(|length| *length <= 32)?),
+                None => (from.trim(), 32)
+            };
+            let to = to.trim().split('/').next()?;
+            let mask = match length {
+                0 => 0,
+                length => u32::MAX << (32 - length)
+            };
+            let network = u32::from(Ipv4Addr::from_str(network.trim()).ok()?);
+            let new = u32::from(Ipv4Addr::from_str(to.trim()).ok()?);
+            Some((network & mask, mask, new & mask))
+        });
+        match parsed {
+            Some(parsed) => result.push(parsed),
+            None => { error(&("Bad address mapping ".to_owned() + rule)); return None; }
+        }
+    }
+    Some(result)
+}
+
+/* "80=8080, 53=5353" */
+fn parse_ports(text: &str) -> Option<HashMap<u16, u16>> {
+    let mut result = HashMap::new();
+    for rule in text.split(',').map(str::trim).filter(|rule| !rule.is_empty()) {
+        match rule.split_once('=').and_then(|(from, to)| Some((from.trim().parse().ok()?, to.trim().parse().ok()?))) {
+            Some((from, to)) => { result.insert(from, to); },
+            None => { error(&("Bad port mapping ".to_owned() + rule)); return None; }
+        }
+    }
+    Some(result)
+}
+
+fn parse_mac(entry: &gtk::Entry, what: &str) -> Option<Option<MacAddr>> {
+    if entry.text_length() == 0 { return Some(None); }
+    match MacAddr::from_str(entry.text().replace('.', ":").as_str()) {
+        Ok(address) => Some(Some(address)),
+        Err(_) => { error(&("Bad ".to_owned() + what + " value")); None }
+    }
+}
+
+pub(crate) struct ReplayOptions {
+    file: gtk::Entry,
+    source_mac: gtk::Entry,
+    destination_mac: gtk::Entry,
+    networks: gtk::Entry,
+    ports: gtk::Entry,
+    checksums: gtk::CheckButton,
+    timing: gtk::DropDown,
+    timing_value: gtk::Entry,
+    loops: gtk::Entry,
+    forever: gtk::CheckButton
+}
+impl ReplayOptions {
+    pub(crate) fn show_window(widgets: Rc<RefCell<MainWindowWidgets>>, database: Rc<RefCell<Database>>) {
+        let replay_widgets = ReplayOptions::new();
+        let dialog = gtk::Dialog::with_buttons(
+            Some("Replay pcap file"),
+            Some(&gtk::Window::new()),
+            gtk::DialogFlags::USE_HEADER_BAR,
+            &[("Start", gtk::ResponseType::Ok), ("Cancel", gtk::ResponseType::Cancel)]);
+        dialog.content_area().append(&replay_widgets.prepare_ui_fields());
+
+        dialog.connect_response(move |dialog, response| {
+            match response {
+                gtk::ResponseType::Ok => {
+                    let (frames, loops) = match replay_widgets.frames() {
+                        Some(value) => value,
+                        None => return
+                    };
+                    let iface = widgets.borrow().get_active_interface();
+                    let offline = widgets.borrow().is_offline();
+                    database.borrow_mut().transmit(frames, loops, &iface, offline);
+                    dialog.close();
+                },
+                gtk::ResponseType::Cancel => {
+                    dialog.close();
+                },
+                _ => {}
+            }
+        });
+
+        dialog.show();
+    }
+
+    fn prepare_ui_fields(&self) -> gtk::Grid {
+        let grid = gtk::Grid::builder().margin_start(24).margin_end(24).margin_top(24).margin_bottom(24).row_spacing(24)
+            .halign(gtk::Align::Center).valign(gtk::Align::Center).column_spacing(24).build();
+
+        let browse = gtk::Button::with_label("Browse...");
+        let file = self.file.clone();
+        browse.connect_clicked(move |_| {
+            let chooser = gtk::FileChooserDialog::new(Some("Open pcap file"), Some(&gtk::Window::new()), gtk::FileChooserAction::Open,
+                                                      &[("Open", gtk::ResponseType::Accept), ("Cancel", gtk::ResponseType::Cancel)]);
+            let file = file.clone();
+            chooser.connect_response(move |chooser, response| {
+                if response == gtk::ResponseType::Accept {
+                    if let Some(path) = chooser.file().and_then(|chosen| chosen.path()) { file.set_text(&path.to_string_lossy()); }
+                }
+                chooser.close();
+            });
+            chooser.show();
+        });
+
+        grid.attach(&gtk::Label::builder().label("File:").halign(gtk::Align::Start).build(),              0, 0, 1, 1);
+        grid.attach(&self.file,                              1, 0, 2, 1);
+        grid.attach(&browse,                                 3, 0, 1, 1);
+        grid.attach(&gtk::Label::builder().label("Source MAC:").halign(gtk::Align::Start).build(),        0, 1, 1, 1);
+        grid.attach(&self.source_mac,                        1, 1, 1, 1);
+        grid.attach(&gtk::Label::builder().label("Destination MAC:").halign(gtk::Align::Start).build(),   2, 1, 1, 1);
+        grid.attach(&self.destination_mac,                   3, 1, 1, 1);
+        grid.attach(&gtk::Label::builder().label("Addresses:").halign(gtk::Align::Start).build(),         0, 2, 1, 1);
+        grid.attach(&self.networks,                          1, 2, 1, 1);
+        grid.attach(&gtk::Label::builder().label("Ports:").halign(gtk::Align::Start).build(),             2, 2, 1, 1);
+        grid.attach(&self.ports,                             3, 2, 1, 1);
+        grid.attach(&self.checksums,                         0, 3, 2, 1);
+        grid.attach(&gtk::Label::builder().label("Timing:").halign(gtk::Align::Start).build(),            0, 4, 1, 1);
+        grid.attach(&self.timing,                            1, 4, 1, 1);
+        grid.attach(&self.timing_value,                      2, 4, 1, 1);
+        grid.attach(&gtk::Label::builder().label("Loops:").halign(gtk::Align::Start).build(),             0, 5, 1, 1);
+        grid.attach(&self.loops,                             1, 5, 1, 1);
+        grid.attach(&self.forever,                           2, 5, 2, 1);
+
+        grid
+    }
+
+    fn new() -> ReplayOptions {
+        ReplayOptions {
+            file: gtk::Entry::builder().placeholder_text("capture.pcap").hexpand(true).build(),
+            source_mac: gtk::Entry::builder().placeholder_text("Keep").build(),
+            destination_mac: gtk::Entry::builder().placeholder_text("Keep").build(),
+            networks: gtk::Entry::builder().placeholder_text("10.0.0.0/8=192.168.0.0").build(),
+            ports: gtk::Entry::builder().placeholder_text("80=8080").build(),
+            checksums: gtk::CheckButton::builder().label("Recompute checksums").active(true).build(),
+            timing: gtk::DropDown::from_strings(&["Original timing", "Speed multiplier", "Fixed rate (packets/s)", "As fast as possible"]),
+            timing_value: gtk::Entry::builder().placeholder_text("Multiplier or rate").build(),
+            loops: gtk::Entry::builder().placeholder_text("1").build(),
+            forever: gtk::CheckButton::with_label("Loop until stopped")
+        }
+    }
+
+    fn frames(&self) -> Option<(Vec<Transmission>, Option<usize>)> {
+        let rewrite = Rewrite {
+            source: parse_mac(&self.source_mac, "source MAC address")?,
+            destination: parse_mac(&self.destination_mac, "destination MAC address")?,
+            networks: parse_networks(&self.networks.text())?,
+            ports: parse_ports(&self.ports.text())?,
+            checksums: self.checksums.is_active()
+        };
+
+        let value = match self.timing.selected() {
+            1 | 2 => match parse_entry(&self.timing_value, 1.0f64, "timing")? {
+                value if value > 0.0 && value.is_finite() => value,
+                _ => { error("The multiplier and the rate must be above zero."); return None; }
+            },
+            _ => 1.0
+        };
+        let loops = match self.forever.is_active() {
+            true => None,
+            false => match parse_entry(&self.loops, 1usize, "loop count")? {
+                0 => { error("The loop count must be at least 1."); return None; }
+                loops => Some(loops)
+            }
+        };
+
+        let records = match pcap::read(&self.file.text()) {
+            Ok(records) if !records.is_empty() => records,
+            Ok(_) => { error("The capture holds no packets."); return None; }
+            Err(what) => { error(&what); return None; }
+        };
+
+        let mut previous = records[0].time;
+        let mut frames = Vec::with_capacity(records.len());
+        for (index, mut record) in records.into_iter().enumerate() {
+            let gap = record.time.saturating_sub(previous);
+            previous = record.time;
+            let delay = match self.timing.selected() {
+                0 => Ok(gap),
+                1 => Duration::try_from_secs_f64(gap.as_secs_f64() / value),
+                2 if index > 0 => Duration::try_from_secs_f64(1.0 / value),
+                _ => Ok(Duration::ZERO)
+            };
+            let delay = match delay {
+                /* Original gaps are kept whatever their length, scaled ones are capped like sequence delays. */
+                Ok(delay) if delay <= sequence::MAX_DELAY || self.timing.selected() == 0 => delay,
+                _ => { error(&format!("Packet {} would wait more than {}, raise the multiplier or the rate.", index, format_delay(sequence::MAX_DELAY))); return None; }
+            };
+            rewrite.apply(&mut record.data);
+            frames.push(Transmission { index, frame: record.data, delay, repeat: 1 });
+        }
+        Some((frames, loops))
+    }
+}
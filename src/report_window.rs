@@ -0,0 +1,23 @@
+use gtk::prelude::*;
+
+pub(crate) struct ReportWindow {
+    window: gtk::Window,
+    lines: gtk::Box
+}
+impl ReportWindow {
+    pub(crate) fn new(title: &str) -> Self {
+        let lines = gtk::Box::builder().orientation(gtk::Orientation::Vertical).spacing(6)
+            .margin_start(12).margin_end(12).margin_top(12).margin_bottom(12).build();
+
+        let scrolled_window = gtk::ScrolledWindow::builder().hscrollbar_policy(gtk::PolicyType::Never)
+            .child(&lines).min_content_height(240).build();
+        let window = gtk::Window::builder().title(title)
+            .default_width(480).default_height(300).child(&scrolled_window).build();
+
+        Self { window, lines }
+    }
+    pub(crate) fn append(&self, text: &str) {
+        self.lines.append(&gtk::Label::builder().label(text).halign(gtk::Align::Start).selectable(true).build());
+    }
+    pub(crate) fn show(&self) { self.window.show(); }
+}
@@ -0,0 +1,295 @@
+use std::cell::RefCell;
+use std::net::Ipv4Addr;
+use std::rc::Rc;
+use std::str::FromStr;
+use gtk::prelude::*;
+use pnet::packet::ip::IpNextHeaderProtocol;
+use rand::Rng;
+use crate::database::Database;
+
+use crate::error_window::error;
+use crate::widgets::MainWindowWidgets;
+use crate::widgets::parse_entry;
+use crate::widgets::parse_hex;
+use crate::widgets::parse_number;
+use crate::widgets::unescape;
+
+/* Chunk names of RFC 4960 with their types. */
+const CHUNK_TYPES: [(u8, &str); 13] = [(0, "data"), (1, "init"), (2, "init-ack"), (3, "sack"), (4, "heartbeat"), (5, "heartbeat-ack"),
+    (6, "abort"), (7, "shutdown"), (8, "shutdown-ack"), (9, "error"), (10, "cookie-echo"), (11, "cookie-ack"), (14, "shutdown-complete")];
+
+/* CRC32c (Castagnoli), bit by bit as packets are small. */
+pub(crate) fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 { crc = if crc & 1 != 0 { crc >> 1 ^ 0x82f6_3b78 } else { crc >> 1 }; }
+    }
+    !crc
+}
+
+fn pad(data: &mut Vec<u8>) {
+    while data.len() & 3 != 0 { data.push(0); }
+}
+
+/* Type-length-value with the length excluding padding, as used by both chunks and parameters. */
+fn tlv(result: &mut Vec<u8>, kind: u8, flags: Option<u8>, value: &[u8]) {
+    match flags {
+        Some(flags) => {
+            result.extend_from_slice(&[kind, flags]);
+            result.extend_from_slice(&(value.len() as u16 + 4).to_be_bytes());
+        },
+        None => {
+            result.extend_from_slice(&(kind as u16).to_be_bytes());
+            result.extend_from_slice(&(value.len() as u16 + 4).to_be_bytes());
+        }
+    }
+    result.extend_from_slice(value);
+    pad(result);
+}
+
+/* One chunk: NAME [key=value ...] [flag ...] [payload=TEXT]. The payload takes the rest of the line. */
+fn chunk(line: &str) -> Result<Vec<u8>, String> {
+    let bad = || format!("Bad SCTP chunk {}", line);
+    let (fields, payload) = match line.split_once(" payload=") {
+        Some((fields, payload)) => (fields, Some(unescape(payload)?)),
+        None => (line, None)
+    };
+    let mut fields = fields.split_whitespace();
+    let name = fields.next().ok_or_else(bad)?.to_ascii_lowercase();
+
+    let mut values: Vec<(&str, &str)> = Vec::new();
+    let mut switches: Vec<&str> = Vec::new();
+    for field in fields {
+        match field.split_once('=') {
+            Some(value) => values.push(value),
+            None => switches.push(field)
+        }
+    }
+    let number = |key: &str, default: u32| -> Result<u32, String> {
+        match values.iter().find(|(name, _)| *name == key) {
+            Some((_, value)) => parse_number(value).ok_or_else(|| format!("Bad SCTP {} value {}", key, value)),
+            None => Ok(default)
+        }
+    };
+    let hex = |key: &str| -> Result<Option<Vec<u8>>, String> {
+        match values.iter().find(|(name, _)| *name == key) {
+            Some((_, value)) => parse_hex(value).map(Some).ok_or_else(|| format!("Bad SCTP {} value {}", key, value)),
+            None => Ok(None)
+        }
+    };
+    let switch = |name: &str| switches.iter().any(|v| v.eq_ignore_ascii_case(name));
+
+    if name == "raw" {
+        let kind = number("type", 0)? as u8;
+        let value = [hex("value")?.unwrap_or_default(), payload.unwrap_or_default()].concat();
+        let mut result = Vec::new();
+        tlv(&mut result, kind, Some(number("flags", 0)? as u8), &value);
+        return Ok(result);
+    }
+    let kind = match CHUNK_TYPES.iter().find(|(_, known)| *known == name) {
+        Some((kind, _)) => *kind,
+        None => return Err(bad())
+    };
+
+    let mut flags = number("flags", 0)? as u8;
+    let mut value = Vec::new();
+    match kind {
+        0 => {
+            if !values.iter().any(|(name, _)| *name == "flags") {
+                let (first, last) = (!switch("middle") && !switch("last"), !switch("middle") && !switch("first"));
+                flags = (switch("u") as u8) << 2 | (first as u8) << 1 | last as u8;
+            }
+            value.extend_from_slice(&number("tsn", 1)?.to_be_bytes());
+            value.extend_from_slice(&(number("stream", 0)? as u16).to_be_bytes());
+            value.extend_from_slice(&(number("ssn", 0)? as u16).to_be_bytes());
+            value.extend_from_slice(&number("ppid", 0)?.to_be_bytes());
+            value.extend(payload.unwrap_or_default());
+        },
+        1 | 2 => {
+            value.extend_from_slice(&number("tag", rand::thread_rng().gen_range(1..=u32::MAX))?.to_be_bytes());
+            value.extend_from_slice(&number("rwnd", 65535)?.to_be_bytes());
+            value.extend_from_slice(&(number("os", 10)? as u16).to_be_bytes());
+            value.extend_from_slice(&(number("mis", 10)? as u16).to_be_bytes());
+            value.extend_from_slice(&number("tsn", 1)?.to_be_bytes());
+            for (_, address) in values.iter().filter(|(name, _)| *name == "addr") {
+                let address = Ipv4Addr::from_str(address).map_err(|_| format!("Bad SCTP address {}", address))?;
+                tlv(&mut value, 5, None, &address.octets());
+            }
+            if let Some(cookie) = hex("cookie")? { tlv(&mut value, 7, None, &cookie); }
+        },
+        3 => {
+            let gaps = values.iter().filter(|(name, _)| *name == "gap").map(|(_, gap)| {
+                gap.split_once('-').and_then(|(start, end)| Some((start.parse::<u16>().ok()?, end.parse::<u16>().ok()?)))
+                    .ok_or_else(|| format!("Bad SCTP gap block {}", gap))
+            }).collect::<Result<Vec<_>, _>>()?;
+            let duplicates = values.iter().filter(|(name, _)| *name == "dup")
+                .map(|(_, tsn)| parse_number::<u32>(tsn).ok_or_else(|| format!("Bad SCTP duplicate TSN {}", tsn))).collect::<Result<Vec<_>, _>>()?;
+            value.extend_from_slice(&number("tsn", 0)?.to_be_bytes());
+            value.extend_from_slice(&number("rwnd", 65535)?.to_be_bytes());
+            value.extend_from_slice(&(gaps.len() as u16).to_be_bytes());
+            value.extend_from_slice(&(duplicates.len() as u16).to_be_bytes());
+            for (start, end) in gaps { value.extend_from_slice(&start.to_be_bytes()); value.extend_from_slice(&end.to_be_bytes()); }
+            for tsn in duplicates { value.extend_from_slice(&tsn.to_be_bytes()); }
+        },
+        4 | 5 => tlv(&mut value, 1, None, &hex("info")?.unwrap_or_else(|| rand::thread_rng().gen::<[u8; 8]>().to_vec())),
+        6 | 9 => {
+            /* The T bit only exists on ABORT and SHUTDOWN-COMPLETE. */
+            if kind == 6 && switch("t") { flags |= 1; }
+            for (_, cause) in values.iter().filter(|(name, _)| *name == "cause") {
+                let (code, data) = cause.split_once(':').unwrap_or((cause, ""));
+                match (code.parse::<u16>(), parse_hex(data)) {
+                    (Ok(code), Some(data)) => {
+                        value.extend_from_slice(&code.to_be_bytes());
+                        value.extend_from_slice(&(data.len() as u16 + 4).to_be_bytes());
+                        value.extend(data);
+                        pad(&mut value);
+                    },
+                    _ => return Err(format!("Bad SCTP error cause {}", cause))
+                }
+            }
+        },
+        7 => value.extend_from_slice(&number("tsn", 0)?.to_be_bytes()),
+        14 => if switch("t") { flags |= 1; },
+        8 | 11 => {},
+        _ => value.extend(hex("cookie")?.unwrap_or_default())
+    }
+
+    let mut result = Vec::new();
+    tlv(&mut result, kind, Some(flags), &value);
+    Ok(result)
+}
+
+pub(crate) struct SctpOptions {
+    source_port: gtk::Entry,
+    destination_port: gtk::Entry,
+    tag: gtk::Entry,
+    checksum: gtk::Entry,
+    chunks: gtk::TextView
+}
+impl SctpOptions {
+    pub(crate) fn show_window(widgets: Rc<RefCell<MainWindowWidgets>>, database: Rc<RefCell<Database>>, send_now: bool) {
+        let sctp_widgets = SctpOptions::new();
+        let dialog = gtk::Dialog::with_buttons(
+            Some("SCTP options"),
+            Some(&gtk::Window::new()),
+            gtk::DialogFlags::USE_HEADER_BAR,
+            &[("Ok", gtk::ResponseType::Ok), ("Cancel", gtk::ResponseType::Cancel)]);
+        dialog.content_area().append(&sctp_widgets.generate_ui());
+
+        dialog.connect_response(move |dialog, response| {
+            match response {
+                gtk::ResponseType::Ok => {
+                    let sctp_packet = match sctp_widgets.build_packet() {
+                        Some(value) => value,
+                        None => return
+                    };
+
+                    MainWindowWidgets::build_ip_packet(widgets.clone(), sctp_packet, IpNextHeaderProtocol::new(132), database.clone(), "SCTP", send_now);
+                    dialog.close();
+                },
+                gtk::ResponseType::Cancel => {
+                    dialog.close();
+                },
+                _ => {}
+            }
+        });
+
+        dialog.show();
+    }
+
+    fn generate_ui(&self) -> gtk::Box {
+        let grid = gtk::Grid::builder().margin_start(24).margin_end(24).margin_top(24).row_spacing(24)
+            .halign(gtk::Align::Center).valign(gtk::Align::Center).column_spacing(24).build();
+
+        grid.attach(&gtk::Label::builder().label("Source port:").halign(gtk::Align::Start).build(), 0, 0, 1, 1);
+        grid.attach(&self.source_port, 1, 0, 1, 1);
+        grid.attach(&gtk::Label::builder().label("Destination port:").halign(gtk::Align::Start).build(), 2, 0, 1, 1);
+        grid.attach(&self.destination_port, 3, 0, 1, 1);
+
+        grid.attach(&gtk::Label::builder().label("Verification tag:").halign(gtk::Align::Start).build(), 0, 1, 1, 1);
+        grid.attach(&self.tag, 1, 1, 1, 1);
+        grid.attach(&gtk::Label::builder().label("Checksum:").halign(gtk::Align::Start).build(), 2, 1, 1, 1);
+        grid.attach(&self.checksum, 3, 1, 1, 1);
+
+        let hint = "One chunk per line:\n\
+            data tsn= stream= ssn= ppid= [U] [first|middle|last] payload=TEXT\n\
+            init|init-ack tag= rwnd= os= mis= tsn= [addr=IP ...] [cookie=HEX]\n\
+            sack tsn= rwnd= [gap=START-END ...] [dup=TSN ...]\n\
+            heartbeat|heartbeat-ack [info=HEX]    abort [T] [cause=CODE:HEX ...]    error [cause=CODE:HEX ...]\n\
+            shutdown tsn=    shutdown-ack    shutdown-complete [T]    cookie-echo cookie=HEX    cookie-ack\n\
+            raw type= [flags=] [value=HEX] [payload=TEXT]";
+        let chunks_box = gtk::Box::builder().orientation(gtk::Orientation::Vertical).spacing(6)
+            .margin_start(12).margin_end(12).margin_top(12).margin_bottom(12).build();
+        chunks_box.append(&gtk::Label::builder().label(hint).halign(gtk::Align::Start).build());
+        chunks_box.append(&gtk::ScrolledWindow::builder().child(&self.chunks).min_content_height(120).build());
+
+        let main_box = gtk::Box::builder().orientation(gtk::Orientation::Vertical)
+            .halign(gtk::Align::Center).valign(gtk::Align::Center).spacing(24).margin_bottom(24).build();
+        main_box.append(&grid);
+        main_box.append(&gtk::Frame::builder().label("Chunks").child(&chunks_box).margin_start(24).margin_end(24).build());
+
+        main_box
+    }
+
+    pub(crate) fn new() -> SctpOptions {
+        let chunks = gtk::TextView::builder().monospace(true).build();
+        chunks.buffer().set_text("init");
+        SctpOptions {
+            source_port: gtk::Entry::builder().placeholder_text("2905").build(),
+            destination_port: gtk::Entry::builder().placeholder_text("2905").build(),
+            tag: gtk::Entry::builder().placeholder_text("0").build(),
+            checksum: gtk::Entry::builder().placeholder_text("Auto (CRC32c)").build(),
+            chunks
+        }
+    }
+
+    fn build_packet(&self) -> Option<Vec<u8>> {
+        let mut packet = Vec::new();
+        packet.extend_from_slice(&parse_entry(&self.source_port, 2905u16, "SCTP source port")?.to_be_bytes());
+        packet.extend_from_slice(&parse_entry(&self.destination_port, 2905u16, "SCTP destination port")?.to_be_bytes());
+        let tag = match self.tag.text().is_empty() {
+            true => 0,
+            false => match parse_number::<u32>(&self.tag.text()) {
+                Some(value) => value,
+                None => { error("Bad SCTP verification tag value"); return None; }
+            }
+        };
+        packet.extend_from_slice(&tag.to_be_bytes());
+        packet.extend_from_slice(&[0; 4]);
+
+        let buffer = self.chunks.buffer();
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            match chunk(line) {
+                Ok(chunk) => packet.extend(chunk),
+                Err(what) => { error(&what); return None; }
+            }
+        }
+
+        /* The computed CRC goes out least significant byte first, an override is written as shown. */
+        let checksum = match self.checksum.text().is_empty() {
+            true => crc32c(&packet).to_le_bytes(),
+            false => match parse_number::<u32>(&self.checksum.text()) {
+                Some(value) => value.to_be_bytes(),
+                None => { error("Bad SCTP checksum value"); return None; }
+            }
+        };
+        packet[8..12].copy_from_slice(&checksum);
+        Some(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* RFC 3720, B.4 */
+    #[test]
+    fn crc32c_vectors() {
+        assert_eq!(crc32c(&[0; 32]), 0x8a91_36aa);
+        assert_eq!(crc32c(&[0xff; 32]), 0x62a8_ab43);
+        assert_eq!(crc32c(&(0..32).collect::<Vec<u8>>()), 0x46dd_794e);
+        assert_eq!(crc32c(&(0..32).rev().collect::<Vec<u8>>()), 0x113f_db5c);
+    }
+}
@@ -0,0 +1,162 @@
+use gtk::prelude::*;
+use std::net::Ipv4Addr;
+use rand::seq::SliceRandom;
+use pnet::packet::Packet;
+use pnet::packet::tcp::TcpFlags;
+use pnet::packet::tcp::TcpPacket;
+use pnet::packet::tcp::MutableTcpPacket;
+
+use crate::error_window::error;
+use crate::widgets::parse_entry;
+
+struct SegmentOptions {
+    size: usize,
+    overlap: usize,
+    out_of_order: bool
+}
+
+pub(crate) struct SegmentationWidgets {
+    enabled: gtk::CheckButton,
+    size: gtk::Entry,
+    overlap: gtk::Entry,
+
+    /* Tiny segments, out of order */
+    evasions: (gtk::CheckButton, gtk::CheckButton)
+}
+impl SegmentationWidgets {
+    pub(crate) fn new() -> Self {
+        Self {
+            enabled: gtk::CheckButton::with_label("Segment"),
+            size: gtk::Entry::builder().placeholder_text("MSS (1460)").build(),
+            overlap: gtk::Entry::builder().placeholder_text("Conflicting overlap bytes").build(),
+            evasions: (gtk::CheckButton::with_label("Tiny segments"), gtk::CheckButton::with_label("Out of order"))
+        }
+    }
+
+    pub(crate) fn prepare_ui_fields(&self) -> gtk::Frame {
+        let main_box = gtk::Box::builder().orientation(gtk::Orientation::Horizontal).halign(gtk::Align::Center)
+            .margin_start(24).margin_end(24).margin_top(6).margin_bottom(12).valign(gtk::Align::Center).spacing(24).build();
+
+        main_box.append(&self.enabled);
+        main_box.append(&self.size);
+        main_box.append(&self.overlap);
+
+        let evasions_box = gtk::Box::builder().orientation(gtk::Orientation::Horizontal).spacing(6).margin_start(6).margin_end(6).build();
+        evasions_box.append(&self.evasions.0); evasions_box.append(&self.evasions.1);
+        main_box.append(&gtk::Frame::builder().label("Evasions").child(&evasions_box).build());
+
+        gtk::Frame::builder().label("TCP segmentation").child(&main_box).build()
+    }
+
+    /* Returns the segment itself when segmentation is off. Checksums are only recomputed when they are automatic. */
+    pub(crate) fn segment(&self, segment: &[u8], addresses: (Ipv4Addr, Ipv4Addr), checksum: bool) -> Option<Vec<Vec<u8>>> {
+        if !self.enabled.is_active() { return Some(vec![segment.to_vec()]); }
+
+        let size = match self.evasions.0.is_active() {
+            true => 1,
+            false => parse_entry(&self.size, 1460usize, "maximum segment size")?
+        };
+        if size == 0 { error("Maximum segment size must be positive"); return None; }
+
+        let overlap = parse_entry(&self.overlap, 0usize, "segment overlap")?;
+        if overlap >= size { error("Segment overlap must be smaller than the segment size"); return None; }
+
+        Some(split(segment, addresses, checksum, &SegmentOptions {
+            size, overlap,
+            out_of_order: self.evasions.1.is_active()
+        }))
+    }
+}
+
+fn split(segment: &[u8], addresses: (Ipv4Addr, Ipv4Addr), checksum: bool, options: &SegmentOptions) -> Vec<Vec<u8>> {
+    let packet = TcpPacket::new(segment).unwrap();
+    let header_length = (packet.get_data_offset() as usize * 4).clamp(TcpPacket::minimum_packet_size(), segment.len());
+    let header = &segment[..header_length];
+    let payload = &segment[header_length..];
+    let sequence = packet.get_sequence();
+    let flags = packet.get_flags();
+
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while start < payload.len() || ranges.is_empty() {
+        let end = (start + options.size).min(payload.len());
+        ranges.push((start, end));
+        if end == payload.len() { break; }
+        start = end - options.overlap;
+    }
+
+    /* Overlapping bytes repeat earlier data with every bit flipped, so the stream depends on which copy the receiver keeps. */
+    let count = ranges.len();
+    let mut segments: Vec<Vec<u8>> = ranges.iter().enumerate().map(|(i, (start, end))| {
+        let conflicting = match i {
+            0 => 0,
+            _ => options.overlap.min(end - start)
+        };
+        let mut bytes = header.to_vec();
+        bytes.extend(payload[*start..start + conflicting].iter().map(|byte| !byte));
+        bytes.extend_from_slice(&payload[start + conflicting..*end]);
+
+        /* SYN stays on the first segment and takes up one sequence number, PSH, FIN and RST go on the last one. */
+        let mut piece = MutableTcpPacket::new(&mut bytes).unwrap();
+        let syn = match i {
+            0 => 0,
+            _ => (flags & TcpFlags::SYN != 0) as u32
+        };
+        piece.set_sequence(sequence.wrapping_add(*start as u32).wrapping_add(syn));
+        let mut piece_flags = flags & !(TcpFlags::PSH | TcpFlags::SYN | TcpFlags::FIN | TcpFlags::RST);
+        if i == 0 { piece_flags |= flags & TcpFlags::SYN; }
+        if i + 1 == count { piece_flags |= TcpFlags::PSH | flags & (TcpFlags::FIN | TcpFlags::RST); }
+        piece.set_flags(piece_flags);
+        if checksum {
+            piece.set_checksum(pnet::packet::tcp::ipv4_checksum(&piece.to_immutable(), &addresses.0, &addresses.1));
+        }
+        Vec::from(piece.packet())
+    }).collect();
+
+    if options.out_of_order && segments.len() > 1 {
+        let original = segments.clone();
+        segments.shuffle(&mut rand::thread_rng());
+        if segments == original { segments.reverse(); }
+    }
+
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(flags: u16, payload: usize) -> Vec<u8> {
+        let mut bytes = vec![0, 80, 0x30, 0x39, 0, 0, 0x03, 0xe8, 0, 0, 0, 0, 0x50, flags as u8, 0xff, 0xff, 0, 0, 0, 0];
+        bytes.extend((0..payload).map(|i| i as u8));
+        bytes
+    }
+
+    /* Sequence number, flags and payload of every segment. */
+    fn layout(segments: &[Vec<u8>]) -> Vec<(u32, u16, Vec<u8>)> {
+        segments.iter().map(|segment| {
+            let packet = TcpPacket::new(segment).unwrap();
+            (packet.get_sequence(), packet.get_flags(), packet.payload().to_vec())
+        }).collect()
+    }
+
+    fn split_into(segment: &[u8], size: usize, overlap: usize) -> Vec<Vec<u8>> {
+        let addresses = (Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2));
+        split(segment, addresses, false, &SegmentOptions { size, overlap, out_of_order: false })
+    }
+
+    #[test]
+    fn syn_first_and_fin_last() {
+        let (syn, fin, psh) = (TcpFlags::SYN, TcpFlags::FIN, TcpFlags::PSH);
+        assert_eq!(layout(&split_into(&segment(syn | fin, 10), 4, 0)),
+                   [(1000, syn, vec![0, 1, 2, 3]), (1005, 0, vec![4, 5, 6, 7]), (1009, psh | fin, vec![8, 9])]);
+        assert_eq!(layout(&split_into(&segment(TcpFlags::ACK | TcpFlags::RST, 4), 2, 0)),
+                   [(1000, TcpFlags::ACK, vec![0, 1]), (1002, TcpFlags::ACK | psh | TcpFlags::RST, vec![2, 3])]);
+    }
+
+    #[test]
+    fn overlap_conflicts() {
+        assert_eq!(layout(&split_into(&segment(TcpFlags::ACK, 6), 4, 2)),
+                   [(1000, TcpFlags::ACK, vec![0, 1, 2, 3]), (1002, TcpFlags::ACK | TcpFlags::PSH, vec![!2, !3, 4, 5])]);
+    }
+}
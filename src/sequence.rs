@@ -0,0 +1,224 @@
+use std::time::Duration;
+
+/* Expressions of the Send Sequence dialog:
+ *   0,3,5          packets one after another
+ *   2-7            a range of packets
+ *   (0-3)*100      repetition of a packet or a group
+ *   1 @10ms        delay before every packet of the item, in us, ms (default) or s
+ *   loop 5 { 0-4 } the same as (0-4)*5
+ * A delay given inside a group is kept, the outer one only applies where none is set. */
+
+const MAX_STEPS: usize = 1_000_000;
+pub(crate) const MAX_DELAY: Duration = Duration::from_secs(3600);
+
+#[derive(Clone)]
+pub(crate) struct Step {
+    pub(crate) index: usize,
+    pub(crate) delay: Option<Duration>
+}
+
+struct Parser<'a> {
+    chars: Vec<char>,
+    position: usize,
+    packets: usize,
+    text: &'a str
+}
+
+pub(crate) fn parse(text: &str, packets: usize) -> Result<Vec<Step>, String> {
+    let mut parser = Parser { chars: text.chars().collect(), position: 0, packets, text };
+    if parser.at_end() { return Err("The sequence is empty.".to_string()); }
+    let steps = parser.list()?;
+    match parser.at_end() {
+        true => Ok(steps),
+        false => Err(parser.unexpected())
+    }
+}
+
+impl Parser<'_> {
+    fn skip_spaces(&mut self) {
+        while self.chars.get(self.position).map(|c| c.is_whitespace()) == Some(true) { self.position += 1; }
+    }
+    fn at_end(&mut self) -> bool {
+        self.skip_spaces();
+        self.position >= self.chars.len()
+    }
+    fn peek(&mut self) -> Option<char> {
+        self.skip_spaces();
+        self.chars.get(self.position).cloned()
+    }
+    fn accept(&mut self, expected: char) -> bool {
+        let found = self.peek() == Some(expected);
+        if found { self.position += 1; }
+        found
+    }
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.accept(expected) {
+            true => Ok(()),
+            false => Err(format!("Expected '{}' at position {}: {}", expected, self.position + 1, self.text))
+        }
+    }
+    fn unexpected(&mut self) -> String {
+        match self.peek() {
+            Some(found) => format!("Unexpected '{}' at position {}: {}", found, self.position + 1, self.text),
+            None => format!("Unexpected end of the sequence: {}", self.text)
+        }
+    }
+    fn word(&mut self) -> String {
+        self.skip_spaces();
+        let start = self.position;
+        while self.chars.get(self.position).map(|c| c.is_ascii_alphabetic()) == Some(true) { self.position += 1; }
+        self.chars[start..self.position].iter().collect()
+    }
+
+    fn number(&mut self) -> Result<usize, String> {
+        self.skip_spaces();
+        let start = self.position;
+        while self.chars.get(self.position).map(|c| c.is_ascii_digit()) == Some(true) { self.position += 1; }
+        if start == self.position { return Err(self.unexpected()); }
+        let digits: String = self.chars[start..self.position].iter().collect();
+        digits.parse().map_err(|_| format!("Number {} is too large.", digits))
+    }
+
+    fn delay(&mut self) -> Result<Duration, String> {
+        let value = self.number()? as u64;
+        let unit = self.word();
+        let delay = match unit.as_str() {
+            "us" => Duration::from_micros(value),
+            "ms" | "" => Duration::from_millis(value),
+            "s" => Duration::from_secs(value),
+            _ => return Err(format!("Unknown time unit '{}', use us, ms or s.", unit))
+        };
+        match delay <= MAX_DELAY {
+            true => Ok(delay),
+            false => Err(format!("A delay can be at most {}.", format_delay(MAX_DELAY)))
+        }
+    }
+
+    fn packet(&self, index: usize) -> Result<usize, String> {
+        match index < self.packets {
+            true => Ok(index),
+            false => Err(format!("Packet {} is not in the queue, it holds {} packets.", index, self.packets))
+        }
+    }
+
+    fn list(&mut self) -> Result<Vec<Step>, String> {
+        let mut steps = self.item()?;
+        while self.accept(',') {
+            let item = self.item()?;
+            if steps.len() + item.len() > MAX_STEPS { return Err(format!("The sequence is longer than {} packets.", MAX_STEPS)); }
+            steps.extend(item);
+        }
+        Ok(steps)
+    }
+
+    fn item(&mut self) -> Result<Vec<Step>, String> {
+        let mut steps = self.term()?;
+        while self.accept('*') {
+            let count = self.number()?;
+            steps = repeat(steps, count)?;
+        }
+        if self.accept('@') {
+            let delay = self.delay()?;
+            for step in steps.iter_mut() { step.delay = step.delay.or(Some(delay)); }
+        }
+        Ok(steps)
+    }
+
+    fn term(&mut self) -> Result<Vec<Step>, String> {
+        if self.accept('(') {
+            let steps = self.list()?;
+            self.expect(')')?;
+            return Ok(steps);
+        }
+        if self.peek().map(|c| c.is_ascii_alphabetic()) == Some(true) {
+            let start = self.position;
+            if self.word() != "loop" {
+                self.position = start;
+                return Err(self.unexpected());
+            }
+            let count = self.number()?;
+            self.expect('{')?;
+            let steps = self.list()?;
+            self.expect('}')?;
+            return repeat(steps, count);
+        }
+
+        let first = self.number()?;
+        let first = self.packet(first)?;
+        if !self.accept('-') { return Ok(vec![Step { index: first, delay: None }]); }
+        let last = self.number()?;
+        let last = self.packet(last)?;
+        if last < first { return Err(format!("Range {}-{} goes backwards.", first, last)); }
+        Ok((first..=last).map(|index| Step { index, delay: None }).collect())
+    }
+}
+
+/* A delay on its own, as written after '@'. */
+pub(crate) fn parse_delay(text: &str) -> Result<Duration, String> {
+    let mut parser = Parser { chars: text.chars().collect(), position: 0, packets: 0, text };
+    let delay = parser.delay()?;
+    match parser.at_end() {
+        true => Ok(delay),
+        false => Err(parser.unexpected())
+    }
+}
+
+pub(crate) fn format_delay(delay: Duration) -> String {
+    match delay.as_micros() {
+        micros if micros % 1000 != 0 => format!("{}us", micros),
+        micros if micros % 1_000_000 != 0 => format!("{}ms", micros / 1000),
+        micros => format!("{}s", micros / 1_000_000)
+    }
+}
+
+fn repeat(steps: Vec<Step>, count: usize) -> Result<Vec<Step>, String> {
+    if count == 0 { return Err("A repeat count must be at least 1.".to_string()); }
+    if steps.len().saturating_mul(count) > MAX_STEPS { return Err(format!("The sequence is longer than {} packets.", MAX_STEPS)); }
+    Ok(steps.iter().cloned().cycle().take(steps.len() * count).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indices(text: &str, packets: usize) -> Vec<usize> {
+        parse(text, packets).unwrap().iter().map(|step| step.index).collect()
+    }
+
+    #[test]
+    fn lists_and_ranges() {
+        assert_eq!(indices("0, 2-4, 1", 5), [0, 2, 3, 4, 1]);
+        assert_eq!(indices(" 3 - 3 ", 4), [3]);
+    }
+
+    #[test]
+    fn repetition() {
+        assert_eq!(indices("(0-1)*2, 3*2", 4), [0, 1, 0, 1, 3, 3]);
+        assert_eq!(indices("loop 2 { 0, 1 }", 2), indices("(0, 1)*2", 2));
+        assert_eq!(indices("0*2*3", 1).len(), 6);
+    }
+
+    #[test]
+    fn delays() {
+        let steps = parse("(0 @5ms, 1) @1s, 1", 2).unwrap();
+        assert_eq!(steps[0].delay, Some(Duration::from_millis(5)));
+        assert_eq!(steps[1].delay, Some(Duration::from_secs(1)));
+        assert_eq!(steps[2].delay, None);
+        assert_eq!(parse_delay("250us"), Ok(Duration::from_micros(250)));
+        assert_eq!(parse_delay("20"), Ok(Duration::from_millis(20)));
+        for delay in [Duration::from_micros(1500), Duration::from_millis(20), Duration::from_secs(3)] {
+            assert_eq!(parse_delay(&format_delay(delay)), Ok(delay));
+        }
+    }
+
+    #[test]
+    fn errors() {
+        for text in ["", "3-1", "5", "0-5", "0*0", "loop 0 { 0 }", "(0", "loop 2 { 0", "repeat 2 { 0 }", "0 @5h", "0,", "0 1"] {
+            assert!(parse(text, 5).is_err(), "{} should not parse", text);
+        }
+        assert!(parse("(0-4)*1000000", 5).is_err());
+        assert!(parse("0 @18446744073709551615s", 1).is_err());
+        assert!(parse_delay("3601s").is_err());
+        assert_eq!(parse_delay("3600s"), Ok(MAX_DELAY));
+    }
+}
@@ -0,0 +1,396 @@
+use std::cell::RefCell;
+use std::net::Ipv4Addr;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use gtk::glib;
+use gtk::prelude::*;
+use pnet::datalink::DataLinkSender;
+use pnet::datalink::DataLinkReceiver;
+use pnet::packet::Packet;
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::tcp::TcpFlags;
+use pnet::packet::tcp::TcpPacket;
+use pnet::packet::tcp::MutableTcpPacket;
+
+use crate::channel;
+use crate::channel::Channel;
+use crate::dissect;
+use crate::error_window::error;
+use crate::report_window::ReportWindow;
+use crate::template::FrameTemplate;
+use crate::widgets::MainWindowWidgets;
+use crate::widgets::parse_entry;
+use crate::widgets::parse_number;
+use crate::widgets::unescape;
+
+#[derive(Clone, Copy)]
+enum Number { Absolute(u32), Relative(i64) }
+impl Number {
+    fn parse(value: &str) -> Option<Number> {
+        match value.chars().next()? {
+            '+' | '-' => value.parse::<i64>().ok().map(Number::Relative),
+            _ => value.parse::<u32>().ok().map(Number::Absolute)
+        }
+    }
+    fn resolve(&self, current: u32) -> u32 {
+        match self {
+            Number::Absolute(value) => *value,
+            Number::Relative(offset) => current.wrapping_add(*offset as u32)
+        }
+    }
+}
+
+/* One line of the segments editor: an optional block of field overrides in braces followed by the data,
+ * e.g. "{flags=PA seq=-4 ttl=3} GET / HTTP/1.0\r\n". */
+#[derive(Default)]
+struct SegmentSpec {
+    flags: Option<u16>,
+    sequence: Option<Number>,
+    acknowledgement: Option<Number>,
+    window: Option<u16>,
+    urgent: Option<u16>,
+    checksum: Option<u16>,
+    ttl: Option<u8>,
+    data: Vec<u8>
+}
+impl SegmentSpec {
+    fn parse(line: &str) -> Result<SegmentSpec, String> {
+        let mut spec = SegmentSpec::default();
+        let mut data = line;
+
+        if let Some(rest) = line.strip_prefix('{') {
+            let end = rest.find('}').ok_or("Missing '}' in segment overrides")?;
+            for field in rest[..end].split_whitespace() {
+                let (name, value) = field.split_once('=').ok_or("Bad segment override ".to_owned() + field)?;
+                let bad = || "Bad segment override value ".to_owned() + field;
+                match name {
+                    "flags" => spec.flags = Some(parse_flags(value).ok_or_else(bad)?),
+                    "seq" => spec.sequence = Some(Number::parse(value).ok_or_else(bad)?),
+                    "ack" => spec.acknowledgement = Some(Number::parse(value).ok_or_else(bad)?),
+                    "window" => spec.window = Some(value.parse().map_err(|_| bad())?),
+                    "urgent" => spec.urgent = Some(value.parse().map_err(|_| bad())?),
+                    "checksum" => spec.checksum = Some(parse_number(value).ok_or_else(bad)?),
+                    "ttl" => spec.ttl = Some(value.parse().map_err(|_| bad())?),
+                    _ => return Err("Unknown segment override ".to_owned() + name)
+                }
+            }
+            data = rest[end + 1..].trim_start();
+        }
+
+        spec.data = unescape(data)?;
+        Ok(spec)
+    }
+}
+
+pub(crate) fn parse_flags(value: &str) -> Option<u16> {
+    let mut flags = 0u16;
+    for letter in value.chars() {
+        flags |= match letter.to_ascii_uppercase() {
+            'F' => TcpFlags::FIN, 'S' => TcpFlags::SYN, 'R' => TcpFlags::RST, 'P' => TcpFlags::PSH,
+            'A' => TcpFlags::ACK, 'U' => TcpFlags::URG, 'E' => TcpFlags::ECE, 'C' => TcpFlags::CWR,
+            'N' => TcpFlags::NS, '.' => 0,
+            _ => return None
+        };
+    }
+    Some(flags)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Closing { Fin, Rst, Keep }
+
+struct SessionParameters {
+    ports: (u16, u16),
+    initial_sequence: u32,
+    window: u16,
+    segments: Vec<SegmentSpec>,
+    closing: Closing,
+    delay: Duration,
+    timeout: Duration
+}
+
+enum SessionEvent {
+    Sent(String),
+    Received(String),
+    Failed(String),
+    Finished
+}
+
+pub(crate) struct SessionOptions {
+    segments: gtk::TextView,
+    closing_dropdown: gtk::DropDown,
+    delay: gtk::Entry,
+    timeout: gtk::Entry
+}
+impl SessionOptions {
+    pub(crate) fn show_window(widgets: Rc<RefCell<MainWindowWidgets>>) {
+        let session_widgets = SessionOptions::new();
+        let dialog = gtk::Dialog::with_buttons(
+            Some("TCP session"),
+            Some(&gtk::Window::new()),
+            gtk::DialogFlags::USE_HEADER_BAR,
+            &[("Start", gtk::ResponseType::Ok), ("Cancel", gtk::ResponseType::Cancel)]);
+        dialog.content_area().append(&session_widgets.generate_ui());
+
+        dialog.connect_response(move |dialog, response| {
+            match response {
+                gtk::ResponseType::Ok => {
+                    let parameters = match session_widgets.parameters(&widgets.borrow()) {
+                        Some(value) => value,
+                        None => return
+                    };
+
+                    let template = match FrameTemplate::new(&widgets.borrow(), IpNextHeaderProtocols::Tcp) {
+                        Some(value) => value,
+                        None => return
+                    };
+
+                    let iface = widgets.borrow().get_active_interface();
+                    let channel = match channel::open(&iface, widgets.borrow().is_offline(), Some(channel::POLL_INTERVAL)) {
+                        Ok(channel) => channel,
+                        Err(what) => { error(&what); return; }
+                    };
+
+                    start(template, channel, parameters);
+                    dialog.close();
+                },
+                gtk::ResponseType::Cancel => {
+                    dialog.close();
+                },
+                _ => {}
+            }
+        });
+
+        dialog.show();
+    }
+
+    fn generate_ui(&self) -> gtk::Box {
+        let grid = gtk::Grid::builder().row_spacing(24).halign(gtk::Align::Center).valign(gtk::Align::Center).column_spacing(24).build();
+
+        grid.attach(&gtk::Label::builder().label("Close with:").halign(gtk::Align::Start).build(),          0, 0, 1, 1);
+        grid.attach(&self.closing_dropdown,                     1, 0, 1, 1);
+        grid.attach(&gtk::Label::builder().label("Delay (ms):").halign(gtk::Align::Start).build(),          2, 0, 1, 1);
+        grid.attach(&self.delay,                                3, 0, 1, 1);
+        grid.attach(&gtk::Label::builder().label("Reply timeout (ms):").halign(gtk::Align::Start).build(),  4, 0, 1, 1);
+        grid.attach(&self.timeout,                              5, 0, 1, 1);
+
+        let scrolled_window = gtk::ScrolledWindow::builder().child(&self.segments).min_content_height(160).min_content_width(600).build();
+        let segments_frame = gtk::Frame::builder().label("Data segments, one per line: {flags=PA seq=+0 ack=+0 window= urgent= checksum= ttl=} data")
+            .child(&scrolled_window).build();
+
+        let main_box = gtk::Box::builder().orientation(gtk::Orientation::Vertical).margin_start(24).margin_end(24)
+            .margin_top(24).margin_bottom(24).halign(gtk::Align::Center).valign(gtk::Align::Center).spacing(24).build();
+        main_box.append(&gtk::Label::new(Some("Addresses, ports, initial sequence number and window are taken from the main window.\n\
+            The local TCP stack answers the SYN-ACK with RST unless outgoing resets are filtered.")));
+        main_box.append(&grid);
+        main_box.append(&segments_frame);
+
+        main_box
+    }
+
+    fn new() -> SessionOptions {
+        SessionOptions {
+            segments: gtk::TextView::builder().monospace(true).build(),
+            closing_dropdown: gtk::DropDown::from_strings(&["FIN", "RST", "Keep open"]),
+            delay: gtk::Entry::builder().placeholder_text("200").build(),
+            timeout: gtk::Entry::builder().placeholder_text("2000").build()
+        }
+    }
+    fn parameters(&self, widgets: &MainWindowWidgets) -> Option<SessionParameters> {
+        let buffer = self.segments.buffer();
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+
+        let mut segments = Vec::new();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            match SegmentSpec::parse(line) {
+                Ok(spec) => segments.push(spec),
+                Err(what) => { error(&what); return None; }
+            }
+        }
+
+        let closing = match self.closing_dropdown.selected() {
+            0 => Closing::Fin,
+            1 => Closing::Rst,
+            _ => Closing::Keep
+        };
+
+        Some(SessionParameters {
+            ports: widgets.tcp_widgets.get_ports()?,
+            initial_sequence: widgets.tcp_widgets.get_initial_sequence()?,
+            window: widgets.tcp_widgets.get_window(64240)?,
+            segments, closing,
+            delay: Duration::from_millis(parse_entry(&self.delay, 200u64, "segment delay")?),
+            timeout: Duration::from_millis(parse_entry(&self.timeout, 2000u64, "reply timeout")?)
+        })
+    }
+}
+
+fn start(template: FrameTemplate, channel: Channel, parameters: SessionParameters) {
+    let report = ReportWindow::new(&format!("TCP session {}:{}", template.destination(), parameters.ports.1));
+    report.show();
+
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    thread::spawn(move || {
+        let (mut tx, mut rx) = channel;
+        let mut session = Session { template, parameters: &parameters, sender: &sender, established: false, send_next: 0, receive_next: 0 };
+        if let Err(what) = session.run(&mut tx, &mut rx) {
+            let _ = sender.send(SessionEvent::Failed(what));
+        }
+        let _ = sender.send(SessionEvent::Finished);
+    });
+
+    receiver.attach(None, move |event| {
+        match event {
+            SessionEvent::Sent(line) => report.append(&("→ ".to_owned() + &line)),
+            SessionEvent::Received(line) => report.append(&("← ".to_owned() + &line)),
+            SessionEvent::Failed(what) => report.append(&what),
+            SessionEvent::Finished => { report.append("Session finished"); return glib::Continue(false); }
+        }
+        glib::Continue(true)
+    });
+}
+
+struct Incoming { flags: u16, sequence: u32, acknowledgement: u32, length: u32 }
+
+struct Session<'a> {
+    template: FrameTemplate,
+    parameters: &'a SessionParameters,
+    sender: &'a glib::Sender<SessionEvent>,
+    established: bool,
+    send_next: u32,
+    receive_next: u32
+}
+impl Session<'_> {
+    fn run(&mut self, tx: &mut Box<dyn DataLinkSender>, rx: &mut Box<dyn DataLinkReceiver>) -> Result<(), String> {
+        let isn = self.parameters.initial_sequence;
+
+        /* Handshake */
+        let syn = SegmentSpec { flags: Some(TcpFlags::SYN), sequence: Some(Number::Absolute(isn)), acknowledgement: Some(Number::Absolute(0)), ..Default::default() };
+        self.send(tx, &syn)?;
+
+        let mut answer = None;
+        self.receive(rx, Instant::now() + self.parameters.timeout, |incoming| {
+            let accepted = incoming.flags & (TcpFlags::SYN | TcpFlags::ACK) == TcpFlags::SYN | TcpFlags::ACK
+                && incoming.acknowledgement == isn.wrapping_add(1);
+            let refused = incoming.flags & TcpFlags::RST != 0;
+            if accepted || refused { answer = Some((accepted, incoming.sequence)); }
+            answer.is_some()
+        })?;
+        match answer {
+            Some((true, sequence)) => self.receive_next = sequence.wrapping_add(1),
+            Some((false, _)) => return Err("Connection refused".to_string()),
+            None => return Err("No SYN-ACK received".to_string())
+        }
+        self.send_next = isn.wrapping_add(1);
+        self.established = true;
+        self.send(tx, &SegmentSpec { flags: Some(TcpFlags::ACK), ..Default::default() })?;
+
+        /* Data */
+        for spec in &self.parameters.segments {
+            self.send(tx, spec)?;
+            self.receive(rx, Instant::now() + self.parameters.delay, |_| false)?;
+        }
+
+        /* Teardown */
+        match self.parameters.closing {
+            Closing::Fin => {
+                self.send(tx, &SegmentSpec { flags: Some(TcpFlags::FIN | TcpFlags::ACK), ..Default::default() })?;
+                /* A RST ends the wait as well but is never acknowledged. */
+                let mut fin_received = false;
+                self.receive(rx, Instant::now() + self.parameters.timeout, |incoming| {
+                    fin_received = incoming.flags & TcpFlags::FIN != 0;
+                    fin_received || incoming.flags & TcpFlags::RST != 0
+                })?;
+                if fin_received { self.send(tx, &SegmentSpec { flags: Some(TcpFlags::ACK), ..Default::default() })?; }
+            },
+            Closing::Rst => self.send(tx, &SegmentSpec { flags: Some(TcpFlags::RST | TcpFlags::ACK), ..Default::default() })?,
+            Closing::Keep => {}
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, tx: &mut Box<dyn DataLinkSender>, spec: &SegmentSpec) -> Result<(), String> {
+        let flags = spec.flags.unwrap_or(match spec.data.is_empty() {
+            true => TcpFlags::ACK,
+            false => TcpFlags::PSH | TcpFlags::ACK
+        });
+        let sequence = spec.sequence.map_or(self.send_next, |value| value.resolve(self.send_next));
+        let acknowledgement = spec.acknowledgement.map_or(self.receive_next, |value| value.resolve(self.receive_next));
+
+        let mut packet = MutableTcpPacket::owned(vec![0u8; MutableTcpPacket::minimum_packet_size() + spec.data.len()]).unwrap();
+        packet.set_source(self.parameters.ports.0);
+        packet.set_destination(self.parameters.ports.1);
+        packet.set_sequence(sequence);
+        packet.set_acknowledgement(acknowledgement);
+        packet.set_data_offset(5);
+        packet.set_flags(flags);
+        packet.set_window(spec.window.unwrap_or(self.parameters.window));
+        packet.set_urgent_ptr(spec.urgent.unwrap_or(0));
+        packet.set_payload(&spec.data);
+        packet.set_checksum(match spec.checksum {
+            Some(checksum) => checksum,
+            None => pnet::packet::tcp::ipv4_checksum(&packet.to_immutable(), &self.template.source(), &self.template.destination())
+        });
+
+        let mut template = self.template.clone();
+        if let Some(ttl) = spec.ttl { template.set_ttl(ttl); }
+        let frame = template.build(packet.packet());
+        match tx.send_to(&frame, None) {
+            Some(Ok(_)) => { let _ = self.sender.send(SessionEvent::Sent(dissect::summary(&frame))); },
+            _ => return Err("Failed to send segment".to_string())
+        }
+
+        /* SYN and FIN take a sequence number each. Overlapping segments never move the next sequence back. */
+        let mut length = spec.data.len() as u32;
+        if flags & TcpFlags::SYN != 0 { length += 1; }
+        if flags & TcpFlags::FIN != 0 { length += 1; }
+        let end = sequence.wrapping_add(length);
+        if (end.wrapping_sub(self.send_next) as i32) > 0 { self.send_next = end; }
+        Ok(())
+    }
+
+    /* Logs every segment of the reverse flow, advances the acknowledgement over in-order data once the
+     * connection is established and stops as soon as `handle` returns true. */
+    fn receive(&mut self, rx: &mut Box<dyn DataLinkReceiver>, deadline: Instant, mut handle: impl FnMut(&Incoming) -> bool) -> Result<(), String> {
+        let destination = self.template.destination();
+        let ports = self.parameters.ports;
+        let sender = self.sender;
+        let established = self.established;
+        let mut receive_next = self.receive_next;
+
+        channel::receive_until(rx, deadline, |frame| {
+            let incoming = match parse_incoming(frame, destination, ports) {
+                Some(incoming) => incoming,
+                None => return false
+            };
+            let _ = sender.send(SessionEvent::Received(dissect::summary(frame)));
+
+            if established && incoming.sequence == receive_next {
+                receive_next = receive_next.wrapping_add(incoming.length);
+                if incoming.flags & TcpFlags::FIN != 0 { receive_next = receive_next.wrapping_add(1); }
+            }
+            handle(&incoming)
+        })?;
+
+        self.receive_next = receive_next;
+        Ok(())
+    }
+}
+
+fn parse_incoming(frame: &[u8], destination: Ipv4Addr, ports: (u16, u16)) -> Option<Incoming> {
+    let ip = Ipv4Packet::new(dissect::ipv4_payload(frame)?)?;
+    if ip.get_source() != destination || ip.get_next_level_protocol() != IpNextHeaderProtocols::Tcp { return None; }
+
+    let tcp = TcpPacket::new(ip.payload())?;
+    if tcp.get_source() != ports.1 || tcp.get_destination() != ports.0 { return None; }
+
+    Some(Incoming {
+        flags: tcp.get_flags(),
+        sequence: tcp.get_sequence(),
+        acknowledgement: tcp.get_acknowledgement(),
+        length: tcp.payload().len() as u32
+    })
+}
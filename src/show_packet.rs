@@ -1,16 +1,156 @@
-pub(crate) fn show(object_type: &str, values: &Vec<u8>) {
-    println!("{}:", object_type);
-    for i in (0..values.len()).step_by(16) {
-        let next_len = if i + 8 < values.len() {
-            i + 8 } else { values.len() };
-
-        print!("{:02x?}", &values[i..next_len]);
-        print!("\t");
-        if i + 8 < values.len() {
-            let next_len = if i + 16 < values.len() {
-                i + 16 } else { values.len() };
-            println!("{:02x?}", &values[i + 8..next_len]);
+use std::cell::RefCell;
+use std::rc::Rc;
+use gtk::prelude::*;
+
+use crate::dissect;
+use crate::dissect::Field;
+use crate::error_window::error;
+use crate::widgets::parse_hex;
+
+/* Bytes of a stored packet, shared with the view so that edits are seen by both. */
+pub(crate) type SharedPacket = Rc<RefCell<Vec<u8>>>;
+
+const HEX_LINE: usize = 6 + 16 * 3 + 1 + 16 + 1;
+
+fn hex_dump(frame: &[u8]) -> String {
+    let mut result = String::new();
+    for (i, line) in frame.chunks(16).enumerate() {
+        let hex: String = line.iter().map(|v| format!("{:02x} ", v)).collect();
+        let ascii: String = line.iter().map(|v| if v.is_ascii_graphic() || *v == b' ' { *v as char } else { '.' }).collect();
+        result += &format!("{:04x}  {:48} {}\n", i * 16, hex, ascii);
+    }
+    result
+}
+
+/* Field tree of the selected packet next to its hex and ASCII dump. Selecting a field highlights its bytes
+ * in both columns of the dump. */
+#[derive(Clone)]
+pub(crate) struct PacketView {
+    store: gtk::TreeStore,
+    tree: gtk::TreeView,
+    hex: gtk::TextView,
+    current: Rc<RefCell<Option<SharedPacket>>>
+}
+impl PacketView {
+    pub(crate) fn new() -> PacketView {
+        let store = gtk::TreeStore::new(&[String::static_type(), String::static_type(), u32::static_type(), u32::static_type()]);
+        let tree = gtk::TreeView::with_model(&store);
+        for (title, column) in [("Field", 0), ("Value", 1)] {
+            let renderer = gtk::CellRendererText::new();
+            tree.append_column(&gtk::TreeViewColumn::with_attributes(title, &renderer, &[("text", column)]));
         }
+
+        let hex = gtk::TextView::builder().monospace(true).editable(false).cursor_visible(false).build();
+        hex.buffer().create_tag(Some("selected"), &[("background", &"#f6d32d")]);
+
+        let view = hex.clone();
+        tree.selection().connect_changed(move |selection| {
+            let buffer = view.buffer();
+            buffer.remove_tag_by_name("selected", &buffer.start_iter(), &buffer.end_iter());
+            let (model, iter) = match selection.selected() {
+                Some(selected) => selected,
+                None => return
+            };
+            let offset = model.get::<u32>(&iter, 2) as usize;
+            let length = model.get::<u32>(&iter, 3) as usize;
+            for i in offset..offset + length {
+                let line = (i / 16 * HEX_LINE) as i32;
+                let column = (i % 16) as i32;
+                let hex = line + 6 + column * 3;
+                let ascii = line + 6 + 16 * 3 + 1 + column;
+                buffer.apply_tag_by_name("selected", &buffer.iter_at_offset(hex), &buffer.iter_at_offset(hex + 2));
+                buffer.apply_tag_by_name("selected", &buffer.iter_at_offset(ascii), &buffer.iter_at_offset(ascii + 1));
+            }
+            let mut start = buffer.iter_at_offset((offset / 16 * HEX_LINE) as i32);
+            view.scroll_to_iter(&mut start, 0.0, false, 0.0, 0.0);
+        });
+
+        PacketView { store, tree, hex, current: Rc::new(RefCell::new(None)) }
+    }
+
+    pub(crate) fn prepare_ui(&self) -> gtk::Frame {
+        let main_box = gtk::Box::builder().orientation(gtk::Orientation::Horizontal).spacing(12)
+            .margin_start(6).margin_end(6).margin_top(6).margin_bottom(6).build();
+        main_box.append(&gtk::ScrolledWindow::builder().child(&self.tree).min_content_width(420).min_content_height(240).hexpand(true).build());
+        let hex_box = gtk::Box::builder().orientation(gtk::Orientation::Vertical).spacing(6).build();
+        hex_box.append(&gtk::ScrolledWindow::builder().child(&self.hex).min_content_width(560).min_content_height(240).build());
+        let edit = gtk::Button::builder().label("Edit bytes...").halign(gtk::Align::End).build();
+        let view = self.clone();
+        edit.connect_clicked(move |_| view.edit());
+        hex_box.append(&edit);
+        main_box.append(&hex_box);
+        gtk::Frame::builder().label("Dissection").child(&main_box).build()
+    }
+
+    pub(crate) fn show(&self, packet: &SharedPacket) {
+        *self.current.borrow_mut() = Some(packet.clone());
+        let frame = packet.borrow();
+        self.store.clear();
+        for field in dissect::fields(&frame) { self.insert(None, &field); }
+        self.tree.expand_all();
+        self.hex.buffer().set_text(&hex_dump(&frame));
+    }
+
+    pub(crate) fn clear(&self) {
+        *self.current.borrow_mut() = None;
+        self.store.clear();
+        self.hex.buffer().set_text("");
+    }
+
+    /* Replaces the bytes of the shown packet. Lengths and checksums are only recomputed on request, so that
+     * deliberately broken values survive the edit. */
+    fn edit(&self) {
+        let packet = match self.current.borrow().clone() {
+            Some(packet) => packet,
+            None => { error("Select a packet first."); return; }
+        };
+
+        let lines: Vec<String> = packet.borrow().chunks(16)
+            .map(|line| line.iter().map(|v| format!("{:02x}", v)).collect::<Vec<_>>().join(" ")).collect();
+        let text = gtk::TextView::builder().monospace(true).build();
+        text.buffer().set_text(&lines.join("\n"));
+        let fix_up = gtk::CheckButton::with_label("Fix lengths and checksums");
+
+        let content = gtk::Box::builder().orientation(gtk::Orientation::Vertical).spacing(12)
+            .margin_start(24).margin_end(24).margin_top(24).margin_bottom(24).build();
+        content.append(&gtk::ScrolledWindow::builder().child(&text).min_content_width(420).min_content_height(320).build());
+        content.append(&fix_up);
+
+        let dialog = gtk::Dialog::with_buttons(
+            Some("Edit packet bytes"),
+            Some(&gtk::Window::new()),
+            gtk::DialogFlags::USE_HEADER_BAR,
+            &[("Ok", gtk::ResponseType::Ok), ("Cancel", gtk::ResponseType::Cancel)]);
+        dialog.content_area().append(&content);
+
+        let view = self.clone();
+        dialog.connect_response(move |dialog, response| {
+            match response {
+                gtk::ResponseType::Ok => {
+                    let buffer = text.buffer();
+                    let mut bytes = match parse_hex(&buffer.text(&buffer.start_iter(), &buffer.end_iter(), false)) {
+                        Some(bytes) => bytes,
+                        None => { error("Bad hex bytes"); return; }
+                    };
+                    if fix_up.is_active() { dissect::fix_up(&mut bytes); }
+
+                    *packet.borrow_mut() = bytes;
+                    view.show(&packet);
+                    dialog.close();
+                },
+                gtk::ResponseType::Cancel => {
+                    dialog.close();
+                },
+                _ => {}
+            }
+        });
+
+        dialog.show();
+    }
+
+    fn insert(&self, parent: Option<&gtk::TreeIter>, field: &Field) {
+        let iter = self.store.insert_with_values(parent, None, &[(0, &field.name), (1, &field.value),
+            (2, &(field.offset as u32)), (3, &(field.length as u32))]);
+        for child in &field.children { self.insert(Some(&iter), child); }
     }
-    println!();
-}
\ No newline at end of file
+}
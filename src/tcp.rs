@@ -4,12 +4,17 @@ use rand::Rng;
 use std::net::Ipv4Addr;
 use pnet::packet::Packet;
 use pnet::packet::FromPacket;
+use pnet::packet::tcp::TcpFlags;
 use pnet::packet::tcp::TcpOption;
 use pnet::packet::tcp::TcpOptionNumbers;
 use pnet::packet::tcp::MutableTcpPacket;
 use pnet::packet::tcp::MutableTcpOptionPacket;
 
 use crate::error_window::error;
+use crate::widgets::set_auto_field;
+use crate::widgets::escape;
+use crate::widgets::entry_data;
+use crate::templates::template_button;
 
 pub(crate) struct TCPWidgets {
     source_port: (gtk::CheckButton, gtk::Entry),
@@ -27,6 +32,7 @@ pub(crate) struct TCPWidgets {
             gtk::CheckButton, gtk::CheckButton, gtk::CheckButton),
 
     data: gtk::Entry,
+    escapes: gtk::CheckButton,
 
     reserved_bits: (gtk::CheckButton, gtk::CheckButton, gtk::CheckButton),
 
@@ -51,6 +57,7 @@ impl TCPWidgets {
                     gtk::CheckButton::with_label("ECE"), gtk::CheckButton::with_label("CWR")),
 
             data: gtk::Entry::builder().placeholder_text("Enter data").margin_end(6).margin_start(6).margin_top(6).margin_bottom(6).build(),
+            escapes: gtk::CheckButton::with_label("Escapes"),
 
             reserved_bits: (gtk::CheckButton::with_label("1"), gtk::CheckButton::with_label("2"), gtk::CheckButton::with_label("3")),
             options: gtk::Entry::builder().placeholder_text("Option 1, Option 2 ...").margin_end(6).margin_start(6).margin_top(6).margin_bottom(6).build()
@@ -226,6 +233,8 @@ impl TCPWidgets {
                 let data_box = gtk::Box::builder().orientation(gtk::Orientation::Horizontal).spacing(6).build();
                 data_box.append(&gtk::Label::new(Some("Data (Various):")));
                 data_box.append(&self.data);
+                data_box.append(&self.escapes);
+                data_box.append(&template_button(&self.data, &self.escapes));
                 lower_box.append(&data_box);
             }
 
@@ -297,31 +306,49 @@ impl TCPWidgets {
         }
         return Some(options);
     }
-    pub(crate) fn build_packet(&self, addresses: (Ipv4Addr, Ipv4Addr)) -> Option<Vec<u8>> {
-        let packet_size = MutableTcpPacket::minimum_packet_size() + self.data.text().len();
-
-        let mut packet = MutableTcpPacket::owned(vec![0u8; packet_size]).unwrap();
-        packet.set_payload(self.data.text().as_bytes());
-
-        if self.source_port.0.is_active() {
-            let mut rng = rand::thread_rng();
-            packet.set_source(rng.gen_range(49152..65535));
-        } else {
-            match self.source_port.1.text().parse::<u16>() {
-                Ok(value) => packet.set_source(value),
+    pub(crate) fn get_ports(&self) -> Option<(u16, u16)> {
+        let source = match self.source_port.0.is_active() {
+            true => rand::thread_rng().gen_range(49152..65535),
+            false => match self.source_port.1.text().parse::<u16>() {
+                Ok(value) => value,
                 Err(_) => { error("Bad tcp source port number"); return None; }
             }
-        }
+        };
 
-        if self.dest_port.0.is_active() {
-            let mut rng = rand::thread_rng();
-            packet.set_destination(rng.gen_range(49152..65535));
-        } else {
-            match self.dest_port.1.text().parse::<u16>() {
-                Ok(value) => packet.set_destination(value),
+        let destination = match self.dest_port.0.is_active() {
+            true => rand::thread_rng().gen_range(49152..65535),
+            false => match self.dest_port.1.text().parse::<u16>() {
+                Ok(value) => value,
                 Err(_) => { error("Bad tcp destination port number"); return None; }
             }
+        };
+
+        Some((source, destination))
+    }
+    pub(crate) fn get_initial_sequence(&self) -> Option<u32> {
+        if self.sequence_number.0.is_active() { return Some(rand::thread_rng().gen()); }
+        match self.sequence_number.1.text().parse::<u32>() {
+            Ok(value) => Some(value),
+            Err(_) => { error("Bad tcp sequence number"); None }
         }
+    }
+    pub(crate) fn get_window(&self, default: u16) -> Option<u16> {
+        if self.window.0.is_active() { return Some(default); }
+        match self.window.1.text().parse::<u16>() {
+            Ok(value) => Some(value),
+            Err(_) => { error("Bad tcp window size number"); None }
+        }
+    }
+    pub(crate) fn build_packet(&self, addresses: (Ipv4Addr, Ipv4Addr)) -> Option<Vec<u8>> {
+        let data = self.get_data()?;
+        let packet_size = MutableTcpPacket::minimum_packet_size() + data.len();
+
+        let mut packet = MutableTcpPacket::owned(vec![0u8; packet_size]).unwrap();
+        packet.set_payload(&data);
+
+        let ports = self.get_ports()?;
+        packet.set_source(ports.0);
+        packet.set_destination(ports.1);
 
         if self.sequence_number.0.is_active() {
             packet.set_sequence(0)
@@ -393,11 +420,66 @@ impl TCPWidgets {
 
         return Some(Vec::from(packet.packet()));
     }
+    pub(crate) fn auto_checksum(&self) -> bool { self.checksum.0.is_active() }
+    /* Fields of a stored segment. Only the option kinds known here are kept, by name. */
+    pub(crate) fn load(&self, segment: &[u8]) {
+        if segment.len() < 20 { error("The TCP segment is too short to load."); return; }
+        let word = |offset: usize| u16::from_be_bytes([segment[offset], segment[offset + 1]]);
+        let long = |offset: usize| u32::from_be_bytes([segment[offset], segment[offset + 1], segment[offset + 2], segment[offset + 3]]);
+        let data_offset = ((segment[12] >> 4) as usize * 4).clamp(20, segment.len());
+        let flags = word(12) & 0x01ff;
+
+        set_auto_field(&self.source_port, Some(word(0).to_string()));
+        set_auto_field(&self.dest_port, Some(word(2).to_string()));
+        set_auto_field(&self.sequence_number, Some(long(4).to_string()));
+        set_auto_field(&self.acknowledgement, Some(long(8).to_string()));
+        set_auto_field(&self.offset, if segment[12] >> 4 == 5 { None } else { Some((segment[12] >> 4).to_string()) });
+        set_auto_field(&self.window, Some(word(14).to_string()));
+        set_auto_field(&self.checksum, None);
+        set_auto_field(&self.urgent, Some(word(18).to_string()));
+
+        let names = [(TcpFlags::NS, &self.flags.0), (TcpFlags::ACK, &self.flags.1), (TcpFlags::SYN, &self.flags.2),
+            (TcpFlags::PSH, &self.flags.3), (TcpFlags::FIN, &self.flags.4), (TcpFlags::RST, &self.flags.5),
+            (TcpFlags::URG, &self.flags.6), (TcpFlags::ECE, &self.flags.7), (TcpFlags::CWR, &self.flags.8)];
+        for (flag, button) in names { button.set_active(flags & flag != 0); }
+        let reserved = segment[12] >> 1 & 7;
+        self.reserved_bits.0.set_active(reserved & 1 != 0);
+        self.reserved_bits.1.set_active(reserved & 2 != 0);
+        self.reserved_bits.2.set_active(reserved & 4 != 0);
+
+        let mut options = Vec::new();
+        let mut offset = 20;
+        while offset < data_offset {
+            let name = match segment[offset] {
+                0 => "EOL",
+                1 => "NOP",
+                2 => "MSS",
+                3 => "WSCALE",
+                4 => "SACK_PERMITTED",
+                5 => "SACK",
+                8 => "TIMESTAMPS",
+                _ => ""
+            };
+            if !name.is_empty() { options.push(name); }
+            offset += match segment[offset] {
+                0 | 1 => 1,
+                _ => segment.get(offset + 1).map(|v| (*v as usize).max(2)).unwrap_or(data_offset)
+            };
+        }
+        self.options.set_text(&options.join(", "));
+        self.data.set_text(&escape(&segment[data_offset..]));
+        self.escapes.set_active(true);
+    }
+    /* With Escapes on, data may contain \r, \n, \t, \\ and \xNN escapes, which is how templates are inserted. */
+    fn get_data(&self) -> Option<Vec<u8>> {
+        entry_data(&self.data, &self.escapes)
+    }
+    /* An empty entry is an empty payload, None means that bad escapes were already reported. */
     pub(crate) fn give_payload(&self) -> Option<Vec<u8>> {
         if self.data.text().is_empty() {
-            return None;
+            return Some(Vec::new());
         }
 
-        return Some(self.data.text().bytes().collect())
+        self.get_data()
     }
 }
@@ -0,0 +1,47 @@
+use std::net::Ipv4Addr;
+use pnet::packet::Packet;
+use pnet::packet::ip::IpNextHeaderProtocol;
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::ipv4::MutableIpv4Packet;
+
+use crate::error_window::error;
+use crate::widgets::MainWindowWidgets;
+
+/* Link and IP headers taken from the main window, so that worker threads can stamp out many packets
+ * without touching the gtk widgets. Total length and header checksum are always recomputed. */
+#[derive(Clone)]
+pub(crate) struct FrameTemplate {
+    link: Vec<u8>,
+    ip: Vec<u8>
+}
+impl FrameTemplate {
+    pub(crate) fn new(widgets: &MainWindowWidgets, next_protocol: IpNextHeaderProtocol) -> Option<Self> {
+        if !widgets.plain_ip() { error("Ping, traceroute and TCP sessions work without a tunnel or fragmentation only."); return None; }
+        let ip = widgets.ip_widgets.build_packet(next_protocol, &[])?;
+        let link = widgets.wrap_frame(&[])?;
+        Some(Self { link, ip })
+    }
+
+    pub(crate) fn source(&self) -> Ipv4Addr { Ipv4Packet::new(&self.ip).unwrap().get_source() }
+    pub(crate) fn destination(&self) -> Ipv4Addr { Ipv4Packet::new(&self.ip).unwrap().get_destination() }
+
+    pub(crate) fn set_ttl(&mut self, ttl: u8) {
+        MutableIpv4Packet::new(&mut self.ip).unwrap().set_ttl(ttl);
+    }
+    pub(crate) fn set_identification(&mut self, identification: u16) {
+        MutableIpv4Packet::new(&mut self.ip).unwrap().set_identification(identification);
+    }
+
+    pub(crate) fn build(&self, transport: &[u8]) -> Vec<u8> {
+        let mut ip = self.ip.clone();
+        ip.extend_from_slice(transport);
+
+        let mut packet = MutableIpv4Packet::new(&mut ip).unwrap();
+        packet.set_total_length((self.ip.len() + transport.len()) as u16);
+        packet.set_checksum(pnet::packet::ipv4::checksum(&packet.to_immutable()));
+
+        let mut frame = self.link.clone();
+        frame.extend_from_slice(packet.packet());
+        frame
+    }
+}
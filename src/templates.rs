@@ -0,0 +1,331 @@
+use gtk::prelude::*;
+use rand::Rng;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::error_window::error;
+use crate::widgets::parse_entry;
+use crate::widgets::escape;
+
+/* Seconds between the NTP era (1900) and the Unix epoch. */
+const NTP_EPOCH_OFFSET: u64 = 2_208_988_800;
+
+const DEFAULT_CIPHERS: &str = "1301 1302 1303 c02b c02f c02c c030 cca9 cca8 009c 009d 002f 0035";
+
+struct HttpPage { method: gtk::Entry, host: gtk::Entry, path: gtk::Entry, headers: gtk::TextView, body: gtk::Entry }
+struct TlsPage { sni: gtk::Entry, alpn: gtk::Entry, ciphers: gtk::Entry }
+struct NtpPage { version: gtk::Entry }
+struct SnmpPage { community: gtk::Entry, oid: gtk::Entry, request_id: gtk::Entry }
+struct SyslogPage { facility: gtk::Entry, severity: gtk::Entry, hostname: gtk::Entry, app: gtk::Entry, procid: gtk::Entry, msgid: gtk::Entry, message: gtk::Entry }
+
+struct TemplateWidgets {
+    pages: gtk::Notebook,
+    http: HttpPage,
+    tls: TlsPage,
+    ntp: NtpPage,
+    snmp: SnmpPage,
+    syslog: SyslogPage
+}
+
+/* Button opening the template dialog, which writes the payload into the entry with non-printable bytes escaped
+ * and turns escapes on for it. */
+pub(crate) fn template_button(target: &gtk::Entry, escapes: &gtk::CheckButton) -> gtk::Button {
+    let button = gtk::Button::with_label("Template...");
+    let (target, escapes) = (target.clone(), escapes.clone());
+    button.connect_clicked(move |_| TemplateWidgets::show_window(target.clone(), escapes.clone()));
+    button
+}
+
+fn text_or(entry: &gtk::Entry, default: &str) -> String {
+    match entry.text().is_empty() {
+        true => default.to_string(),
+        false => entry.text().to_string()
+    }
+}
+
+fn entry(placeholder: &str) -> gtk::Entry {
+    gtk::Entry::builder().placeholder_text(placeholder).build()
+}
+
+fn grid(rows: &[(&str, &gtk::Widget)]) -> gtk::Grid {
+    let grid = gtk::Grid::builder().margin_start(24).margin_end(24).margin_top(24).margin_bottom(24).row_spacing(12)
+        .halign(gtk::Align::Center).valign(gtk::Align::Center).column_spacing(24).build();
+    for (i, (label, widget)) in rows.iter().enumerate() {
+        grid.attach(&gtk::Label::builder().label(label).halign(gtk::Align::Start).build(), 0, i as i32, 1, 1);
+        grid.attach(*widget, 1, i as i32, 1, 1);
+    }
+    grid
+}
+
+impl TemplateWidgets {
+    fn new() -> Self {
+        Self {
+            pages: gtk::Notebook::new(),
+            http: HttpPage {
+                method: entry("GET"), host: entry("example.com"), path: entry("/"),
+                headers: gtk::TextView::builder().monospace(true).build(), body: entry("Body")
+            },
+            tls: TlsPage { sni: entry("example.com"), alpn: entry("h2,http/1.1"), ciphers: entry(DEFAULT_CIPHERS) },
+            ntp: NtpPage { version: entry("4") },
+            snmp: SnmpPage { community: entry("public"), oid: entry("1.3.6.1.2.1.1.1.0"), request_id: entry("Request ID (random)") },
+            syslog: SyslogPage {
+                facility: entry("1"), severity: entry("5"), hostname: entry("-"), app: entry("-"),
+                procid: entry("-"), msgid: entry("-"), message: entry("Message")
+            }
+        }
+    }
+
+    fn show_window(target: gtk::Entry, escapes: gtk::CheckButton) {
+        let widgets = TemplateWidgets::new();
+        let dialog = gtk::Dialog::with_buttons(
+            Some("Payload templates"),
+            Some(&gtk::Window::new()),
+            gtk::DialogFlags::USE_HEADER_BAR,
+            &[("Ok", gtk::ResponseType::Ok), ("Cancel", gtk::ResponseType::Cancel)]);
+        dialog.content_area().append(&widgets.generate_ui());
+
+        dialog.connect_response(move |dialog, response| {
+            match response {
+                gtk::ResponseType::Ok => {
+                    if let Some(payload) = widgets.build_payload() {
+                        target.set_text(&escape(&payload));
+                        escapes.set_active(true);
+                        dialog.close();
+                    }
+                },
+                gtk::ResponseType::Cancel => {
+                    dialog.close();
+                },
+                _ => {}
+            }
+        });
+
+        dialog.show();
+    }
+
+    fn generate_ui(&self) -> gtk::Notebook {
+        let headers = gtk::ScrolledWindow::builder().child(&self.http.headers).min_content_height(80).min_content_width(300).build();
+        let http = grid(&[("Method", self.http.method.upcast_ref()), ("Host", self.http.host.upcast_ref()),
+            ("Path", self.http.path.upcast_ref()), ("Headers, one per line", headers.upcast_ref()), ("Body", self.http.body.upcast_ref())]);
+        self.pages.append_page(&http, Some(&gtk::Label::new(Some("HTTP request"))));
+
+        let tls = grid(&[("Server name", self.tls.sni.upcast_ref()), ("ALPN", self.tls.alpn.upcast_ref()),
+            ("Cipher suites, hex", self.tls.ciphers.upcast_ref())]);
+        self.pages.append_page(&tls, Some(&gtk::Label::new(Some("TLS ClientHello"))));
+
+        let ntp = grid(&[("Version", self.ntp.version.upcast_ref())]);
+        self.pages.append_page(&ntp, Some(&gtk::Label::new(Some("NTP request"))));
+
+        let snmp = grid(&[("Community", self.snmp.community.upcast_ref()), ("OID", self.snmp.oid.upcast_ref()),
+            ("Request ID", self.snmp.request_id.upcast_ref())]);
+        self.pages.append_page(&snmp, Some(&gtk::Label::new(Some("SNMPv2c GET"))));
+
+        let syslog = grid(&[("Facility", self.syslog.facility.upcast_ref()), ("Severity", self.syslog.severity.upcast_ref()),
+            ("Hostname", self.syslog.hostname.upcast_ref()), ("App name", self.syslog.app.upcast_ref()),
+            ("Process ID", self.syslog.procid.upcast_ref()), ("Message ID", self.syslog.msgid.upcast_ref()),
+            ("Message", self.syslog.message.upcast_ref())]);
+        self.pages.append_page(&syslog, Some(&gtk::Label::new(Some("Syslog"))));
+
+        self.pages.clone()
+    }
+
+    fn build_payload(&self) -> Option<Vec<u8>> {
+        match self.pages.current_page() {
+            Some(0) => Some(self.http()),
+            Some(1) => self.tls(),
+            Some(2) => self.ntp(),
+            Some(3) => self.snmp(),
+            _ => self.syslog()
+        }
+    }
+
+    fn http(&self) -> Vec<u8> {
+        let buffer = self.http.headers.buffer();
+        let headers = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+        let body = self.http.body.text();
+
+        let mut request = format!("{} {} HTTP/1.1\r\nHost: {}\r\n", text_or(&self.http.method, "GET"),
+                                  text_or(&self.http.path, "/"), text_or(&self.http.host, "example.com"));
+        for header in headers.lines().filter(|line| !line.trim().is_empty()) {
+            request += header;
+            request += "\r\n";
+        }
+        if !body.is_empty() { request += &format!("Content-Length: {}\r\n", body.len()); }
+        request += "\r\n";
+        request += &body;
+        request.into_bytes()
+    }
+
+    /* A TLS 1.3 capable ClientHello with a random key share, like the ones browsers send. */
+    fn tls(&self) -> Option<Vec<u8>> {
+        let sni = text_or(&self.tls.sni, "example.com");
+        let alpn = text_or(&self.tls.alpn, "h2,http/1.1");
+        let ciphers_text = text_or(&self.tls.ciphers, DEFAULT_CIPHERS);
+        let ciphers = match ciphers_text.split([' ', ',']).filter(|v| !v.is_empty()).map(|v| u16::from_str_radix(v.trim_start_matches("0x"), 16))
+            .collect::<Result<Vec<_>, _>>() {
+            Ok(ciphers) => ciphers,
+            Err(_) => { error("Bad cipher suite list"); return None; }
+        };
+
+        let mut rng = rand::thread_rng();
+        let mut extensions = Vec::new();
+        let mut server_name = vec![0];
+        server_name.extend(with_length16(sni.as_bytes()));
+        extension(&mut extensions, 0, &with_length16(&server_name));
+        extension(&mut extensions, 10, &with_length16(&[0x00, 0x1d, 0x00, 0x17, 0x00, 0x18]));
+        extension(&mut extensions, 11, &[1, 0]);
+        extension(&mut extensions, 13, &with_length16(&[0x04, 0x03, 0x08, 0x04, 0x04, 0x01, 0x05, 0x03, 0x08, 0x05, 0x05, 0x01, 0x02, 0x01]));
+        let mut protocols = Vec::new();
+        for protocol in alpn.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+            if protocol.len() > 255 { error("ALPN protocol names are at most 255 bytes long"); return None; }
+            protocols.push(protocol.len() as u8);
+            protocols.extend_from_slice(protocol.as_bytes());
+        }
+        extension(&mut extensions, 16, &with_length16(&protocols));
+        extension(&mut extensions, 43, &[4, 0x03, 0x04, 0x03, 0x03]);
+        extension(&mut extensions, 45, &[1, 1]);
+        let mut key_share = vec![0x00, 0x1d, 0x00, 0x20];
+        key_share.extend((0..32).map(|_| rng.gen::<u8>()));
+        extension(&mut extensions, 51, &with_length16(&key_share));
+
+        let mut hello = vec![0x03, 0x03];
+        hello.extend((0..32).map(|_| rng.gen::<u8>()));
+        hello.push(32);
+        hello.extend((0..32).map(|_| rng.gen::<u8>()));
+        hello.extend(with_length16(&ciphers.iter().flat_map(|cipher| cipher.to_be_bytes()).collect::<Vec<u8>>()));
+        hello.extend_from_slice(&[1, 0]);
+        hello.extend(with_length16(&extensions));
+
+        let mut handshake = vec![1];
+        handshake.extend_from_slice(&(hello.len() as u32).to_be_bytes()[1..]);
+        handshake.append(&mut hello);
+
+        let mut record = vec![0x16, 0x03, 0x01];
+        record.extend(with_length16(&handshake));
+        Some(record)
+    }
+
+    fn ntp(&self) -> Option<Vec<u8>> {
+        let version = parse_entry(&self.ntp.version, 4u8, "NTP version")?;
+        let mut packet = vec![0u8; 48];
+        packet[0] = (version & 0x07) << 3 | 3;
+        packet[2] = 6;
+        packet[3] = 0xec;
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let seconds = (now.as_secs() + NTP_EPOCH_OFFSET) as u32;
+        let fraction = ((now.subsec_nanos() as u64) << 32) / 1_000_000_000;
+        packet[40..44].copy_from_slice(&seconds.to_be_bytes());
+        packet[44..48].copy_from_slice(&(fraction as u32).to_be_bytes());
+        Some(packet)
+    }
+
+    fn snmp(&self) -> Option<Vec<u8>> {
+        let community = text_or(&self.snmp.community, "public");
+        let oid_text = text_or(&self.snmp.oid, "1.3.6.1.2.1.1.1.0");
+        let oid = match encode_oid(&oid_text) {
+            Some(oid) => oid,
+            None => { error("Bad SNMP OID value"); return None; }
+        };
+        let request_id = parse_entry(&self.snmp.request_id, rand::thread_rng().gen_range(1..i32::MAX), "SNMP request ID")?;
+
+        let varbind = ber(0x30, &[ber(0x06, &oid), ber(0x05, &[])].concat());
+        let pdu = ber(0xa0, &[ber_integer(request_id), ber_integer(0), ber_integer(0), ber(0x30, &varbind)].concat());
+        Some(ber(0x30, &[ber_integer(1), ber(0x04, community.as_bytes()), pdu].concat()))
+    }
+
+    fn syslog(&self) -> Option<Vec<u8>> {
+        let facility = parse_entry(&self.syslog.facility, 1u8, "syslog facility")?;
+        let severity = parse_entry(&self.syslog.severity, 5u8, "syslog severity")?;
+        if facility > 23 || severity > 7 { error("Syslog facility must be below 24 and severity below 8"); return None; }
+
+        let field = |entry: &gtk::Entry| match entry.text().is_empty() {
+            true => "-".to_string(),
+            false => entry.text().replace(' ', "_")
+        };
+        Some(format!("<{}>1 {} {} {} {} {} - {}", facility as u16 * 8 + severity as u16, timestamp(), field(&self.syslog.hostname),
+                     field(&self.syslog.app), field(&self.syslog.procid), field(&self.syslog.msgid), self.syslog.message.text()).into_bytes())
+    }
+}
+
+fn with_length16(data: &[u8]) -> Vec<u8> {
+    let mut result = (data.len() as u16).to_be_bytes().to_vec();
+    result.extend_from_slice(data);
+    result
+}
+
+fn extension(extensions: &mut Vec<u8>, kind: u16, data: &[u8]) {
+    extensions.extend_from_slice(&kind.to_be_bytes());
+    extensions.extend(with_length16(data));
+}
+
+fn ber(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut result = vec![tag];
+    match content.len() {
+        length if length < 0x80 => result.push(length as u8),
+        length if length < 0x100 => result.extend_from_slice(&[0x81, length as u8]),
+        length => { result.push(0x82); result.extend_from_slice(&(length as u16).to_be_bytes()); }
+    }
+    result.extend_from_slice(content);
+    result
+}
+
+fn ber_integer(value: i32) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let mut start = 0;
+    while start < 3 && ((bytes[start] == 0 && bytes[start + 1] & 0x80 == 0) || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0)) {
+        start += 1;
+    }
+    ber(0x02, &bytes[start..])
+}
+
+fn encode_oid(text: &str) -> Option<Vec<u8>> {
+    let arcs = text.trim_start_matches('.').split('.').map(|v| v.parse::<u32>().ok()).collect::<Option<Vec<_>>>()?;
+    /* The first two arcs share a subidentifier, the second is below 40 unless the first is 2. */
+    if arcs.len() < 2 || arcs[0] > 2 || (arcs[0] < 2 && arcs[1] >= 40) { return None; }
+    let first = arcs[0].checked_mul(40)?.checked_add(arcs[1])?;
+
+    let mut result = Vec::new();
+    for arc in std::iter::once(first).chain(arcs[2..].iter().cloned()) {
+        let mut chunk = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            chunk.insert(0, (rest & 0x7f) as u8 | 0x80);
+            rest >>= 7;
+        }
+        result.append(&mut chunk);
+    }
+    Some(result)
+}
+
+/* RFC 3339 UTC timestamp without a date library, days converted with the civil calendar algorithm. */
+fn timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (days, seconds) = ((now.as_secs() / 86400) as i64, now.as_secs() % 86400);
+
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60, now.subsec_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn object_identifiers() {
+        assert_eq!(encode_oid("1.3.6.1.2.1.1.5.0"), Some(vec![0x2b, 6, 1, 2, 1, 1, 5, 0]));
+        assert_eq!(encode_oid(".2.999.1"), Some(vec![0x88, 0x37, 1]));
+        for text in ["1", "3.1", "1.40", "0.4294967295", "2.4294967295", "1.3.x"] {
+            assert_eq!(encode_oid(text), None, "{}", text);
+        }
+    }
+}
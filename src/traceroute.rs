@@ -0,0 +1,345 @@
+use std::cell::RefCell;
+use std::net::Ipv4Addr;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use gtk::glib;
+use gtk::prelude::*;
+use rand::Rng;
+use pnet::packet::Packet;
+use pnet::packet::icmp::IcmpPacket;
+use pnet::packet::icmp::IcmpTypes;
+use pnet::packet::icmp::echo_reply::EchoReplyPacket;
+use pnet::packet::ip::IpNextHeaderProtocol;
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::tcp::TcpFlags;
+use pnet::packet::tcp::TcpPacket;
+use pnet::packet::tcp::MutableTcpPacket;
+use pnet::packet::udp::MutableUdpPacket;
+
+use crate::channel;
+use crate::channel::Channel;
+use crate::dissect;
+use crate::error_window::error;
+use crate::ping::echo_request;
+use crate::report_window::ReportWindow;
+use crate::template::FrameTemplate;
+use crate::widgets::MainWindowWidgets;
+use crate::widgets::parse_entry;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Probe { Udp, Icmp, TcpSyn }
+impl Probe {
+    fn protocol(&self) -> IpNextHeaderProtocol {
+        match self {
+            Probe::Udp => IpNextHeaderProtocols::Udp,
+            Probe::Icmp => IpNextHeaderProtocols::Icmp,
+            Probe::TcpSyn => IpNextHeaderProtocols::Tcp
+        }
+    }
+}
+
+struct TracerouteParameters {
+    probe: Probe,
+    first_ttl: u8,
+    max_ttl: u8,
+    probes: u8,
+    timeout: Duration,
+    source_port: u16,
+    destination_port: u16,
+    constant_flow: bool
+}
+
+enum Answer { TimeExceeded, Unreachable(u8), Reached }
+
+enum TracerouteEvent {
+    Hop(u8, Vec<Option<(Ipv4Addr, Duration, Answer)>>),
+    Failed(String),
+    Finished
+}
+
+pub(crate) struct TracerouteOptions {
+    probe_dropdown: gtk::DropDown,
+    first_ttl: gtk::Entry,
+    max_ttl: gtk::Entry,
+    probes: gtk::Entry,
+    timeout: gtk::Entry,
+    source_port: gtk::Entry,
+    destination_port: gtk::Entry,
+    constant_flow: gtk::CheckButton
+}
+impl TracerouteOptions {
+    pub(crate) fn show_window(widgets: Rc<RefCell<MainWindowWidgets>>) {
+        let traceroute_widgets = TracerouteOptions::new();
+        let dialog = gtk::Dialog::with_buttons(
+            Some("Traceroute"),
+            Some(&gtk::Window::new()),
+            gtk::DialogFlags::USE_HEADER_BAR,
+            &[("Start", gtk::ResponseType::Ok), ("Cancel", gtk::ResponseType::Cancel)]);
+        dialog.content_area().append(&traceroute_widgets.prepare_ui_fields());
+
+        dialog.connect_response(move |dialog, response| {
+            match response {
+                gtk::ResponseType::Ok => {
+                    let parameters = match traceroute_widgets.parameters() {
+                        Some(value) => value,
+                        None => return
+                    };
+
+                    let template = match FrameTemplate::new(&widgets.borrow(), parameters.probe.protocol()) {
+                        Some(value) => value,
+                        None => return
+                    };
+
+                    let iface = widgets.borrow().get_active_interface();
+                    let channel = match channel::open(&iface, widgets.borrow().is_offline(), Some(channel::POLL_INTERVAL)) {
+                        Ok(channel) => channel,
+                        Err(what) => { error(&what); return; }
+                    };
+
+                    start(template, channel, parameters);
+                    dialog.close();
+                },
+                gtk::ResponseType::Cancel => {
+                    dialog.close();
+                },
+                _ => {}
+            }
+        });
+
+        dialog.show();
+    }
+
+    fn prepare_ui_fields(&self) -> gtk::Grid {
+        let grid = gtk::Grid::builder().margin_start(24).margin_end(24).margin_top(24).margin_bottom(24).row_spacing(24)
+            .halign(gtk::Align::Center).valign(gtk::Align::Center).column_spacing(24).build();
+
+        grid.attach(&gtk::Label::builder().label("Probe:").halign(gtk::Align::Start).build(),            0, 0, 1, 1);
+        grid.attach(&self.probe_dropdown,                    1, 0, 1, 1);
+        grid.attach(&self.constant_flow,                     2, 0, 2, 1);
+        grid.attach(&gtk::Label::builder().label("First TTL:").halign(gtk::Align::Start).build(),        0, 1, 1, 1);
+        grid.attach(&self.first_ttl,                         1, 1, 1, 1);
+        grid.attach(&gtk::Label::builder().label("Max TTL:").halign(gtk::Align::Start).build(),          2, 1, 1, 1);
+        grid.attach(&self.max_ttl,                           3, 1, 1, 1);
+        grid.attach(&gtk::Label::builder().label("Probes per hop:").halign(gtk::Align::Start).build(),   0, 2, 1, 1);
+        grid.attach(&self.probes,                            1, 2, 1, 1);
+        grid.attach(&gtk::Label::builder().label("Timeout (ms):").halign(gtk::Align::Start).build(),     2, 2, 1, 1);
+        grid.attach(&self.timeout,                           3, 2, 1, 1);
+        grid.attach(&gtk::Label::builder().label("Source port:").halign(gtk::Align::Start).build(),      0, 3, 1, 1);
+        grid.attach(&self.source_port,                       1, 3, 1, 1);
+        grid.attach(&gtk::Label::builder().label("Destination port:").halign(gtk::Align::Start).build(), 2, 3, 1, 1);
+        grid.attach(&self.destination_port,                  3, 3, 1, 1);
+
+        grid
+    }
+
+    fn new() -> TracerouteOptions {
+        TracerouteOptions {
+            probe_dropdown: gtk::DropDown::from_strings(&["UDP", "ICMP echo", "TCP SYN"]),
+            first_ttl: gtk::Entry::builder().placeholder_text("1").build(),
+            max_ttl: gtk::Entry::builder().placeholder_text("30").build(),
+            probes: gtk::Entry::builder().placeholder_text("3").build(),
+            timeout: gtk::Entry::builder().placeholder_text("1000").build(),
+            source_port: gtk::Entry::builder().placeholder_text("Random").build(),
+            destination_port: gtk::Entry::builder().placeholder_text("33434 / 80").build(),
+            constant_flow: gtk::CheckButton::builder().label("Constant flow (Paris)").active(true).build()
+        }
+    }
+    fn parameters(&self) -> Option<TracerouteParameters> {
+        let probe = match self.probe_dropdown.selected() {
+            0 => Probe::Udp,
+            1 => Probe::Icmp,
+            2 => Probe::TcpSyn,
+            _ => { error("Unsupported traceroute probe type"); return None; }
+        };
+
+        let first_ttl = parse_entry(&self.first_ttl, 1u8, "first TTL")?;
+        let max_ttl = parse_entry(&self.max_ttl, 30u8, "max TTL")?;
+        if first_ttl == 0 || first_ttl > max_ttl {
+            error("Bad traceroute TTL range");
+            return None;
+        }
+
+        let probes = parse_entry(&self.probes, 3u8, "probes per hop")?;
+        let timeout = parse_entry(&self.timeout, 1000u64, "traceroute timeout")?;
+        let source_port = parse_entry(&self.source_port, rand::thread_rng().gen_range(49152..65535), "source port")?;
+        let default_port = match probe {
+            Probe::TcpSyn => 80,
+            _ => 33434
+        };
+        let destination_port = parse_entry(&self.destination_port, default_port, "destination port")?;
+
+        Some(TracerouteParameters {
+            probe, first_ttl, max_ttl, probes,
+            timeout: Duration::from_millis(timeout),
+            source_port, destination_port,
+            constant_flow: self.constant_flow.is_active()
+        })
+    }
+}
+
+fn start(template: FrameTemplate, channel: Channel, parameters: TracerouteParameters) {
+    let destination = template.destination();
+
+    let report = ReportWindow::new(&format!("Traceroute {}", destination));
+    report.append(&format!("traceroute to {}, {} hops max", destination, parameters.max_ttl));
+    report.show();
+
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    thread::spawn(move || run(template, channel, parameters, sender));
+
+    receiver.attach(None, move |event| {
+        match event {
+            TracerouteEvent::Hop(ttl, answers) => report.append(&format_hop(ttl, &answers)),
+            TracerouteEvent::Failed(what) => report.append(&what),
+            TracerouteEvent::Finished => return glib::Continue(false)
+        }
+        glib::Continue(true)
+    });
+}
+
+fn format_hop(ttl: u8, answers: &[Option<(Ipv4Addr, Duration, Answer)>]) -> String {
+    let mut line = format!("{:2} ", ttl);
+    let mut last_address = None;
+    for answer in answers {
+        match answer {
+            Some((address, rtt, kind)) => {
+                if last_address != Some(*address) {
+                    line += &format!(" {}", address);
+                    last_address = Some(*address);
+                }
+                line += &format!("  {:.3} ms", rtt.as_secs_f64() * 1000.0);
+                if let Answer::Unreachable(code) = kind {
+                    line += match code {
+                        0 => " !N", 1 => " !H", 2 => " !P", 4 => " !F", 13 => " !X",
+                        _ => " !"
+                    };
+                }
+            },
+            None => line += "  *"
+        }
+    }
+    line
+}
+
+fn run(mut template: FrameTemplate, channel: Channel, parameters: TracerouteParameters, sender: glib::Sender<TracerouteEvent>) {
+    let (mut tx, mut rx) = channel;
+    let destination = template.destination();
+    let identification_base: u16 = rand::thread_rng().gen();
+
+    let mut index = 0u16;
+    for ttl in parameters.first_ttl..=parameters.max_ttl {
+        template.set_ttl(ttl);
+
+        let mut answers = Vec::with_capacity(parameters.probes as usize);
+        for _ in 0..parameters.probes {
+            index = index.wrapping_add(1);
+            let identification = identification_base.wrapping_add(index);
+            template.set_identification(identification);
+
+            let frame = template.build(&build_probe(&template, &parameters, index));
+            let sent = Instant::now();
+            match tx.send_to(&frame, None) {
+                Some(Ok(_)) => {},
+                _ => { let _ = sender.send(TracerouteEvent::Failed("Failed to send probe with TTL ".to_owned() + &ttl.to_string())); return; }
+            }
+
+            let mut answer = None;
+            let result = channel::receive_until(&mut rx, sent + parameters.timeout, |frame| {
+                answer = match_answer(frame, destination, &parameters, identification, index)
+                    .map(|(address, kind)| (address, sent.elapsed(), kind));
+                answer.is_some()
+            });
+            if let Err(what) = result { let _ = sender.send(TracerouteEvent::Failed(what)); return; }
+            answers.push(answer);
+        }
+
+        let reached = answers.iter().flatten().any(|(_, _, kind)| !matches!(kind, Answer::TimeExceeded));
+        let _ = sender.send(TracerouteEvent::Hop(ttl, answers));
+        if reached { break; }
+    }
+
+    let _ = sender.send(TracerouteEvent::Finished);
+}
+
+fn build_probe(template: &FrameTemplate, parameters: &TracerouteParameters, index: u16) -> Vec<u8> {
+    /* In constant flow mode everything a load balancer may hash stays the same for every probe:
+     * probes are told apart by IP identification, TCP sequence or ICMP sequence only. */
+    match parameters.probe {
+        Probe::Udp => {
+            let destination_port = match parameters.constant_flow {
+                true => parameters.destination_port,
+                false => parameters.destination_port.wrapping_add(index - 1)
+            };
+
+            let mut packet = MutableUdpPacket::owned(vec![0u8; MutableUdpPacket::minimum_packet_size() + 32]).unwrap();
+            packet.set_source(parameters.source_port);
+            packet.set_destination(destination_port);
+            packet.set_length((MutableUdpPacket::minimum_packet_size() + 32) as u16);
+            packet.set_checksum(pnet::packet::udp::ipv4_checksum(&packet.to_immutable(), &template.source(), &template.destination()));
+            Vec::from(packet.packet())
+        },
+        Probe::Icmp => {
+            /* Echo checksum lies where the ports of other protocols are, so the sequence number
+             * change is compensated by the first payload word to keep it constant. */
+            let mut payload = vec![0u8; 32];
+            if parameters.constant_flow {
+                payload[..2].copy_from_slice(&(0xffff - index).to_be_bytes());
+            }
+            echo_request(std::process::id() as u16, index, &payload)
+        },
+        Probe::TcpSyn => {
+            let mut packet = MutableTcpPacket::owned(vec![0u8; MutableTcpPacket::minimum_packet_size()]).unwrap();
+            packet.set_source(parameters.source_port);
+            packet.set_destination(parameters.destination_port);
+            packet.set_sequence(index as u32);
+            packet.set_data_offset(5);
+            packet.set_flags(TcpFlags::SYN);
+            packet.set_window(64240);
+            packet.set_checksum(pnet::packet::tcp::ipv4_checksum(&packet.to_immutable(), &template.source(), &template.destination()));
+            Vec::from(packet.packet())
+        }
+    }
+}
+
+fn match_answer(frame: &[u8], destination: Ipv4Addr, parameters: &TracerouteParameters, identification: u16, index: u16) -> Option<(Ipv4Addr, Answer)> {
+    let ip = Ipv4Packet::new(dissect::ipv4_payload(frame)?)?;
+
+    match ip.get_next_level_protocol() {
+        IpNextHeaderProtocols::Icmp => {
+            let icmp = IcmpPacket::new(ip.payload())?;
+            match icmp.get_icmp_type() {
+                IcmpTypes::TimeExceeded | IcmpTypes::DestinationUnreachable => {
+                    /* Errors quote our IP header after the four unused bytes. */
+                    let quoted = Ipv4Packet::new(icmp.payload().get(4..)?)?;
+                    if quoted.get_destination() != destination || quoted.get_identification() != identification
+                        || quoted.get_next_level_protocol() != parameters.probe.protocol() { return None; }
+
+                    let answer = match icmp.get_icmp_type() {
+                        IcmpTypes::TimeExceeded => Answer::TimeExceeded,
+                        _ if icmp.get_icmp_code().0 == 3 && ip.get_source() == destination => Answer::Reached,
+                        _ => Answer::Unreachable(icmp.get_icmp_code().0)
+                    };
+                    Some((ip.get_source(), answer))
+                },
+                IcmpTypes::EchoReply if parameters.probe == Probe::Icmp && ip.get_source() == destination => {
+                    let reply = EchoReplyPacket::new(ip.payload())?;
+                    if reply.get_identifier() != std::process::id() as u16 || reply.get_sequence_number() != index { return None; }
+                    Some((ip.get_source(), Answer::Reached))
+                },
+                _ => None
+            }
+        },
+        IpNextHeaderProtocols::Tcp if parameters.probe == Probe::TcpSyn && ip.get_source() == destination => {
+            let tcp = TcpPacket::new(ip.payload())?;
+            if tcp.get_source() != parameters.destination_port || tcp.get_destination() != parameters.source_port
+                || tcp.get_acknowledgement() != index as u32 + 1 { return None; }
+            if tcp.get_flags() & (TcpFlags::SYN | TcpFlags::RST) == 0 { return None; }
+            Some((ip.get_source(), Answer::Reached))
+        },
+        _ => None
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use gtk::glib;
+use pnet::datalink::DataLinkSender;
+
+use crate::channel;
+use crate::error_window::error;
+
+/* One frame of a timed run. The index only names the frame in error messages. */
+pub(crate) struct Transmission {
+    pub(crate) index: usize,
+    pub(crate) frame: Vec<u8>,
+    pub(crate) delay: Duration,
+    pub(crate) repeat: usize
+}
+
+/* Sends the frames from a worker thread, so that delays keep the window responsive. Delays count from when the
+ * previous frame was due, so that they do not drift, unless sending fell behind. No loop count repeats the frames
+ * until the first returned flag is set. The second one stays set for as long as the thread sends. */
+pub(crate) fn start(tx: Box<dyn DataLinkSender>, frames: Vec<Transmission>, loops: Option<usize>) -> (Arc<AtomicBool>, Arc<AtomicBool>) {
+    let stop = Arc::new(AtomicBool::new(false));
+    let sending = Arc::new(AtomicBool::new(true));
+    let (stopped, running) = (stop.clone(), sending.clone());
+
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    thread::spawn(move || {
+        if let Err(what) = run(tx, &frames, loops, &stopped) { let _ = sender.send(what); }
+        running.store(false, Ordering::Relaxed);
+    });
+    receiver.attach(None, move |what: String| {
+        error(&what);
+        glib::Continue(true)
+    });
+
+    (stop, sending)
+}
+
+fn run(mut tx: Box<dyn DataLinkSender>, frames: &[Transmission], loops: Option<usize>, stop: &AtomicBool) -> Result<(), String> {
+    let mut next = Instant::now();
+    let mut pass = 0;
+    while loops.map(|loops| pass < loops).unwrap_or(true) {
+        for transmission in frames {
+            for _ in 0..transmission.repeat {
+                next = match next.checked_add(transmission.delay) {
+                    Some(next) => next,
+                    None => return Err("The delay before packet ".to_owned() + &transmission.index.to_string() + " is too long")
+                };
+                if !wait_until(next, stop) { return Ok(()); }
+                next = next.max(Instant::now());
+                match tx.send_to(&transmission.frame, None) {
+                    Some(Ok(_)) => {},
+                    _ => return Err("Failed to send packet ".to_owned() + &transmission.index.to_string())
+                }
+            }
+        }
+        pass += 1;
+    }
+    Ok(())
+}
+
+/* Sleeps most of the way and spins through the last millisecond, returns false when stopped. */
+fn wait_until(deadline: Instant, stop: &AtomicBool) -> bool {
+    loop {
+        if stop.load(Ordering::Relaxed) { return false; }
+        let now = Instant::now();
+        if now >= deadline { return true; }
+        match deadline - now {
+            remaining if remaining > Duration::from_millis(2) => thread::sleep((remaining - Duration::from_millis(1)).min(channel::POLL_INTERVAL)),
+            _ => std::hint::spin_loop()
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use gtk::prelude::*;
+use rand::Rng;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use pnet::packet::ip::IpNextHeaderProtocol;
+
+use crate::error_window::error;
+use crate::ip::IPWidgets;
+use crate::udp;
+use crate::widgets::parse_entry;
+use crate::widgets::parse_hex;
+use crate::widgets::parse_number;
+
+const TRANSPARENT_ETHERNET: u16 = 0x6558;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Tunnel { Gre, IpInIp, Vxlan, Geneve }
+
+pub(crate) struct TunnelWidgets {
+    enabled: gtk::CheckButton,
+    kind: gtk::DropDown,
+    inner: gtk::DropDown,
+    outer_source: gtk::Entry,
+    outer_destination: gtk::Entry,
+
+    /* GRE */
+    key: gtk::Entry,
+    sequence: gtk::Entry,
+    checksum: gtk::CheckButton,
+
+    /* VXLAN and Geneve */
+    vni: gtk::Entry,
+    ports: (gtk::Entry, gtk::Entry),
+    options: gtk::Entry
+}
+impl TunnelWidgets {
+    pub(crate) fn new() -> Self {
+        Self {
+            enabled: gtk::CheckButton::with_label("Encapsulate"),
+            kind: gtk::DropDown::from_strings(&["GRE", "IP-in-IP", "VXLAN", "Geneve"]),
+            inner: gtk::DropDown::from_strings(&["Inner IP packet", "Inner Ethernet frame"]),
+            outer_source: gtk::Entry::builder().placeholder_text("Outer source IP").build(),
+            outer_destination: gtk::Entry::builder().placeholder_text("Outer destination IP").build(),
+
+            key: gtk::Entry::builder().placeholder_text("GRE key").build(),
+            sequence: gtk::Entry::builder().placeholder_text("GRE sequence").build(),
+            checksum: gtk::CheckButton::with_label("GRE checksum"),
+
+            vni: gtk::Entry::builder().placeholder_text("VNI (0)").build(),
+            ports: (gtk::Entry::builder().placeholder_text("UDP source port").build(),
+                    gtk::Entry::builder().placeholder_text("UDP destination port").build()),
+            options: gtk::Entry::builder().placeholder_text("Geneve options: class:type:hex, ...").build()
+        }
+    }
+
+    pub(crate) fn prepare_ui_fields(&self) -> gtk::Frame {
+        let grid = gtk::Grid::builder().margin_start(24).margin_end(24).margin_top(6).margin_bottom(12).halign(gtk::Align::Center)
+            .valign(gtk::Align::Center).row_spacing(12).column_spacing(24).build();
+
+        grid.attach(&self.enabled, 0, 0, 1, 1);
+        grid.attach(&self.kind, 1, 0, 1, 1);
+        grid.attach(&self.inner, 2, 0, 1, 1);
+        grid.attach(&self.outer_source, 3, 0, 1, 1);
+        grid.attach(&self.outer_destination, 4, 0, 1, 1);
+
+        grid.attach(&self.key, 0, 1, 1, 1);
+        grid.attach(&self.sequence, 1, 1, 1, 1);
+        grid.attach(&self.checksum, 2, 1, 1, 1);
+
+        grid.attach(&self.vni, 0, 2, 1, 1);
+        grid.attach(&self.ports.0, 1, 2, 1, 1);
+        grid.attach(&self.ports.1, 2, 2, 1, 1);
+        grid.attach(&self.options, 3, 2, 2, 1);
+
+        gtk::Frame::builder().label("Tunnel").child(&grid).build()
+    }
+
+    fn kind(&self) -> Tunnel {
+        match self.kind.selected() {
+            0 => Tunnel::Gre,
+            1 => Tunnel::IpInIp,
+            2 => Tunnel::Vxlan,
+            _ => Tunnel::Geneve
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool { self.enabled.is_active() }
+    pub(crate) fn enabled_button(&self) -> &gtk::CheckButton { &self.enabled }
+
+    /* Whether the tunnel payload is an Ethernet frame rather than an IP packet. */
+    pub(crate) fn carries_frame(&self) -> bool {
+        match self.kind() {
+            Tunnel::IpInIp => false,
+            Tunnel::Vxlan => true,
+            Tunnel::Gre | Tunnel::Geneve => self.inner.selected() == 1
+        }
+    }
+
+    /* Outer IP packet around the inner packet or frame. The outer header takes every field but the addresses
+     * from the IP section. */
+    pub(crate) fn encapsulate(&self, ip_widgets: &IPWidgets, inner: &[u8]) -> Option<Vec<u8>> {
+        let source = Ipv4Addr::from_str(&self.outer_source.text());
+        let destination = Ipv4Addr::from_str(&self.outer_destination.text());
+        let addresses = match (source, destination) {
+            (Ok(source), Ok(destination)) => (source, destination),
+            _ => { error("Bad outer tunnel IP address value."); return None; }
+        };
+        let protocol_type = match (self.carries_frame(), inner.first().map(|v| v >> 4)) {
+            (true, _) => TRANSPARENT_ETHERNET,
+            (false, Some(6)) => 0x86dd,
+            (false, _) => 0x0800
+        };
+
+        match self.kind() {
+            Tunnel::Gre => {
+                let payload = self.gre(inner, protocol_type)?;
+                ip_widgets.build_packet_between(addresses, IpNextHeaderProtocol::new(47), &payload)
+            },
+            Tunnel::IpInIp => {
+                let protocol = match protocol_type {
+                    0x86dd => 41,
+                    _ => 4
+                };
+                ip_widgets.build_packet_between(addresses, IpNextHeaderProtocol::new(protocol), inner)
+            },
+            Tunnel::Vxlan => {
+                let mut payload = vec![0x08, 0, 0, 0];
+                payload.extend_from_slice(&(self.vni()? << 8).to_be_bytes());
+                payload.extend_from_slice(inner);
+                let ports = self.ports(4789)?;
+                ip_widgets.build_packet_between(addresses, IpNextHeaderProtocol::new(17), &udp::datagram(ports, &payload, addresses))
+            },
+            Tunnel::Geneve => {
+                let options = self.geneve_options()?;
+                let mut payload = vec![(options.len() / 4) as u8, 0];
+                payload.extend_from_slice(&protocol_type.to_be_bytes());
+                payload.extend_from_slice(&(self.vni()? << 8).to_be_bytes());
+                payload.extend(options);
+                payload.extend_from_slice(inner);
+                let ports = self.ports(6081)?;
+                ip_widgets.build_packet_between(addresses, IpNextHeaderProtocol::new(17), &udp::datagram(ports, &payload, addresses))
+            }
+        }
+    }
+
+    fn gre(&self, inner: &[u8], protocol_type: u16) -> Option<Vec<u8>> {
+        let key = match self.key.text().is_empty() {
+            true => None,
+            false => Some(parse_entry::<u32>(&self.key, 0, "GRE key")?)
+        };
+        let sequence = match self.sequence.text().is_empty() {
+            true => None,
+            false => Some(parse_entry::<u32>(&self.sequence, 0, "GRE sequence")?)
+        };
+
+        let mut flags = 0u16;
+        if self.checksum.is_active() { flags |= 0x8000; }
+        if key.is_some() { flags |= 0x2000; }
+        if sequence.is_some() { flags |= 0x1000; }
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&flags.to_be_bytes());
+        header.extend_from_slice(&protocol_type.to_be_bytes());
+        if self.checksum.is_active() { header.extend_from_slice(&[0; 4]); }
+        if let Some(key) = key { header.extend_from_slice(&key.to_be_bytes()); }
+        if let Some(sequence) = sequence { header.extend_from_slice(&sequence.to_be_bytes()); }
+        header.extend_from_slice(inner);
+
+        if self.checksum.is_active() {
+            let checksum = pnet::util::checksum(&header, 2);
+            header[4..6].copy_from_slice(&checksum.to_be_bytes());
+        }
+        Some(header)
+    }
+
+    fn vni(&self) -> Option<u32> {
+        let vni = parse_entry(&self.vni, 0u32, "VNI")?;
+        if vni >= 1 << 24 { error("VNI must fit into 24 bits"); return None; }
+        Some(vni)
+    }
+
+    /* Without a source port a random one from the dynamic range is used, as tunnel endpoints do for entropy. */
+    fn ports(&self, default: u16) -> Option<(u16, u16)> {
+        let source = parse_entry(&self.ports.0, rand::thread_rng().gen_range(49152..65535), "tunnel UDP source port")?;
+        let destination = parse_entry(&self.ports.1, default, "tunnel UDP destination port")?;
+        Some((source, destination))
+    }
+
+    /* Options are written as "class:type:data" with hex data, which is padded to four bytes. */
+    fn geneve_options(&self) -> Option<Vec<u8>> {
+        let mut result = Vec::new();
+        let text = self.options.text();
+        for option in text.split(',').map(str::trim).filter(|option| !option.is_empty()) {
+            let fields: Vec<&str> = option.split(':').collect();
+            let class = fields.first().and_then(|v| parse_number::<u16>(v));
+            let option_type = fields.get(1).and_then(|v| parse_number::<u16>(v)).filter(|v| *v < 256);
+            let data = fields.get(2).map_or(Some(Vec::new()), |v| parse_hex(v));
+
+            let (class, option_type, mut data) = match (class, option_type, data) {
+                (Some(class), Some(option_type), Some(data)) if fields.len() <= 3 && data.len() <= 124 => (class, option_type, data),
+                _ => { error(&("Bad Geneve option ".to_owned() + option)); return None; }
+            };
+            while data.len() & 3 != 0 { data.push(0); }
+
+            result.extend_from_slice(&class.to_be_bytes());
+            result.push(option_type as u8);
+            result.push((data.len() / 4) as u8);
+            result.append(&mut data);
+        }
+        if result.len() > 252 { error("Geneve options are longer than 252 bytes"); return None; }
+        Some(result)
+    }
+}
@@ -2,48 +2,54 @@ use std::cell::RefCell;
 use std::net::Ipv4Addr;
 use std::rc::Rc;
 use gtk::prelude::*;
+use rand::Rng;
 use pnet::packet::ip::IpNextHeaderProtocol;
 use pnet::packet::Packet;
 use pnet::packet::udp::MutableUdpPacket;
 use crate::database::Database;
 
+use crate::dhcp;
+use crate::dhcp::DhcpWidgets;
+use crate::dns::DnsWidgets;
 use crate::error_window::error;
-use crate::show_packet::show;
+use crate::templates::template_button;
 use crate::widgets::MainWindowWidgets;
+use crate::widgets::escape;
+use crate::widgets::entry_data;
 
 pub(crate) struct UdpOptions {
     src_port: gtk::Entry,
     dest_port: gtk::Entry,
     length: gtk::Entry,
     checksum: gtk::Entry,
-    data: gtk::Entry
+    data: gtk::Entry,
+    escapes: gtk::CheckButton,
+
+    pages: gtk::Notebook,
+    dns: DnsWidgets,
+    dhcp: DhcpWidgets
 }
 impl UdpOptions {
-    pub(crate) fn show_window(widgets: Rc<RefCell<MainWindowWidgets>>, addresses: (Ipv4Addr, Ipv4Addr), database: Rc<RefCell<Database>>) {
+    pub(crate) fn show_window(widgets: Rc<RefCell<MainWindowWidgets>>, database: Rc<RefCell<Database>>, send_now: bool) {
         let udp_widgets = UdpOptions::new();
         let dialog = gtk::Dialog::with_buttons(
             Some("UDP options"),
             Some(&gtk::Window::new()),
             gtk::DialogFlags::USE_HEADER_BAR,
             &[("Ok", gtk::ResponseType::Ok), ("Cancel", gtk::ResponseType::Cancel)]);
-        dialog.content_area().append(&Self::generate_ui(&udp_widgets));
+        dialog.content_area().append(&udp_widgets.generate_ui(widgets.clone()));
+        if let Some(datagram) = widgets.borrow_mut().take_loaded(17) { udp_widgets.load(&datagram); }
 
         dialog.connect_response(move |dialog, response| {
             match response {
                 gtk::ResponseType::Ok => {
-                    let udp_packet = match udp_widgets.build_packet(addresses) {
+                    /* Addresses are read here, as the DHCP defaults may have changed them. */
+                    let udp_packet = match udp_widgets.build_packet(&widgets.borrow()) {
                         Some(value) => value,
                         None => { dialog.close(); return }
                     };
 
-                    show("UDP packet", &udp_packet);
-
-                    let ip_packet = match widgets.borrow().ip_widgets.build_packet(IpNextHeaderProtocol::new(17), &udp_packet) {
-                        Some(packet) => packet,
-                        None => { dialog.close(); return }
-                    };
-
-                    MainWindowWidgets::build_frame(widgets.clone(), &ip_packet, database.clone(), "UDP");
+                    MainWindowWidgets::build_ip_packet(widgets.clone(), udp_packet, IpNextHeaderProtocol::new(17), database.clone(), "UDP", send_now);
 
                     dialog.close();
                 },
@@ -57,16 +63,30 @@ impl UdpOptions {
         dialog.show();
     }
 
-    fn generate_ui(&self) -> gtk::Box {
+    fn generate_ui(&self, widgets: Rc<RefCell<MainWindowWidgets>>) -> gtk::Box {
         let fields_grid = self.prepare_ui_fields();
 
         let lower_box = gtk::Box::builder().orientation(gtk::Orientation::Horizontal)
-            .halign(gtk::Align::Center).valign(gtk::Align::Center).spacing(24).margin_start(24).margin_end(24).build();
+            .halign(gtk::Align::Center).valign(gtk::Align::Center).spacing(24).margin_start(24).margin_end(24).margin_top(24).build();
         lower_box.append(&gtk::Label::new(Some("Data"))); lower_box.append(&self.data);
+        lower_box.append(&self.escapes);
+        lower_box.append(&template_button(&self.data, &self.escapes));
+
+        /* The payload is taken from the selected page. */
+        self.pages.append_page(&lower_box, Some(&gtk::Label::new(Some("Data"))));
+        self.pages.append_page(&self.dns.prepare_ui_fields(), Some(&gtk::Label::new(Some("DNS"))));
+
+        let ports = (self.src_port.clone(), self.dest_port.clone());
+        let dhcp_page = self.dhcp.prepare_ui_fields(move || {
+            widgets.borrow().apply_dhcp_defaults();
+            ports.0.set_text(&dhcp::CLIENT_PORT.to_string());
+            ports.1.set_text(&dhcp::SERVER_PORT.to_string());
+        });
+        self.pages.append_page(&dhcp_page, Some(&gtk::Label::new(Some("DHCP"))));
 
         let main_box = gtk::Box::builder().orientation(gtk::Orientation::Vertical)
             .halign(gtk::Align::Center).valign(gtk::Align::Center).spacing(24).margin_top(24).margin_bottom(24).build();
-        main_box.append(&fields_grid); main_box.append(&lower_box);
+        main_box.append(&fields_grid); main_box.append(&self.pages);
 
         main_box
     }
@@ -92,14 +112,31 @@ impl UdpOptions {
             dest_port: gtk::Entry::builder().placeholder_text("Port..").build(),
             length: gtk::Entry::builder().placeholder_text("Length..").build(),
             checksum: gtk::Entry::builder().placeholder_text("Checksum..").build(),
-            data: gtk::Entry::builder().placeholder_text("Data..").build()
+            data: gtk::Entry::builder().placeholder_text("Data..").build(),
+            escapes: gtk::CheckButton::with_label("Escapes"),
+
+            pages: gtk::Notebook::builder().margin_start(24).margin_end(24).build(),
+            dns: DnsWidgets::new(),
+            dhcp: DhcpWidgets::new()
         }
     }
-    pub(crate) fn build_packet(&self, addresses: (Ipv4Addr, Ipv4Addr)) -> Option<Vec<u8>> {
-        let packet_size = MutableUdpPacket::minimum_packet_size() + self.data.text().bytes().len();
+    /* A loaded payload always goes to the Data page, length and checksum are computed again. */
+    fn load(&self, datagram: &[u8]) {
+        if datagram.len() < 8 { error("The UDP datagram is too short to load."); return; }
+        self.src_port.set_text(&u16::from_be_bytes([datagram[0], datagram[1]]).to_string());
+        self.dest_port.set_text(&u16::from_be_bytes([datagram[2], datagram[3]]).to_string());
+        self.data.set_text(&escape(&datagram[8..]));
+        self.escapes.set_active(true);
+        self.pages.set_current_page(Some(0));
+    }
+    pub(crate) fn build_packet(&self, widgets: &MainWindowWidgets) -> Option<Vec<u8>> {
+        let addresses = widgets.ip_widgets.get_addresses()?;
+        let payload = self.payload(widgets)?;
+        let packet_size = MutableUdpPacket::minimum_packet_size() + payload.len();
         let mut packet = MutableUdpPacket::owned(vec![0u8; packet_size]).unwrap();
-        packet.set_payload(self.data.text().as_bytes());
+        packet.set_payload(&payload);
 
+        let default_ports = self.default_ports();
         if self.src_port.text_length() > 0 {
             match self.src_port.text().parse::<u16>() {
                 Ok(port) => packet.set_source(port),
@@ -108,6 +145,8 @@ impl UdpOptions {
                     return None;
                 }
             }
+        } else if let Some(port) = default_ports.0 {
+            packet.set_source(port);
         } else { error("Please specify a source UDP port."); return None; }
 
         if self.dest_port.text_length() > 0 {
@@ -118,6 +157,8 @@ impl UdpOptions {
                     return None;
                 }
             }
+        } else if let Some(port) = default_ports.1 {
+            packet.set_destination(port);
         } else { error("Please specify a destination UDP port."); return None; }
 
         if self.length.text_length() > 0 {
@@ -131,7 +172,7 @@ impl UdpOptions {
         } else {
             packet.set_length(
                 (MutableUdpPacket::minimum_packet_size()
-                    + self.data.text().as_bytes().len()) as u16);
+                    + payload.len()) as u16);
         }
 
 
@@ -155,4 +196,30 @@ impl UdpOptions {
 
         return Some(Vec::from(packet.packet()));
     }
-}
\ No newline at end of file
+    fn payload(&self, widgets: &MainWindowWidgets) -> Option<Vec<u8>> {
+        match self.pages.current_page() {
+            Some(1) => self.dns.build_message(),
+            Some(2) => self.dhcp.build_message(widgets.get_macs()?.0),
+            _ => entry_data(&self.data, &self.escapes)
+        }
+    }
+    /* Ports used when the entries are left empty, depending on the payload page. */
+    fn default_ports(&self) -> (Option<u16>, Option<u16>) {
+        match self.pages.current_page() {
+            Some(1) => (Some(rand::thread_rng().gen_range(49152..65535)), Some(53)),
+            Some(2) => (Some(dhcp::CLIENT_PORT), Some(dhcp::SERVER_PORT)),
+            _ => (None, None)
+        }
+    }
+}
+
+/* Datagram with automatic length and checksum, used for tunnel headers. */
+pub(crate) fn datagram(ports: (u16, u16), payload: &[u8], addresses: (Ipv4Addr, Ipv4Addr)) -> Vec<u8> {
+    let mut packet = MutableUdpPacket::owned(vec![0u8; MutableUdpPacket::minimum_packet_size() + payload.len()]).unwrap();
+    packet.set_source(ports.0);
+    packet.set_destination(ports.1);
+    packet.set_length((MutableUdpPacket::minimum_packet_size() + payload.len()) as u16);
+    packet.set_payload(payload);
+    packet.set_checksum(pnet::packet::udp::ipv4_checksum(&packet.to_immutable(), &addresses.0, &addresses.1));
+    Vec::from(packet.packet())
+}
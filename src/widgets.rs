@@ -1,16 +1,12 @@
 
 use std::cell::RefCell;
+use std::net::Ipv4Addr;
 use std::rc::Rc;
 use std::str::FromStr;
 
 use gtk::prelude::*;
 use pnet::datalink;
 use pnet::util::MacAddr;
-use pnet::packet::Packet;
-use pnet::datalink::Channel::Ethernet;
-use pnet::datalink::NetworkInterface;
-use pnet::packet::ethernet::EtherType;
-use pnet::packet::ethernet::MutableEthernetPacket;
 use pnet::packet::ip::IpNextHeaderProtocol;
 
 use mac_address::get_mac_address;
@@ -21,22 +17,136 @@ use crate::ip::IPWidgets;
 use crate::tcp::TCPWidgets;
 use crate::udp::UdpOptions;
 use crate::icmp::IcmpOptions;
+use crate::igmp::IgmpOptions;
+use crate::icmpv6::Icmpv6Options;
+use crate::sctp::SctpOptions;
+use crate::traceroute::TracerouteOptions;
+use crate::ethernet::EthernetWidgets;
+use crate::fragment::FragmentationWidgets;
+use crate::segment::SegmentationWidgets;
+use crate::tunnel::TunnelWidgets;
+use crate::session::SessionOptions;
+use crate::replay::ReplayOptions;
+use crate::channel;
+use crate::dissect;
 use crate::error_window::error;
-use crate::show_packet::show;
 use crate::database::Database;
 
+pub(crate) fn parse_entry<T: FromStr>(entry: &gtk::Entry, default: T, what: &str) -> Option<T> {
+    if entry.text_length() == 0 { return Some(default); }
+    match entry.text().parse::<T>() {
+        Ok(value) => Some(value),
+        Err(_) => { error(&("Bad ".to_owned() + what + " value")); None }
+    }
+}
+
+/* Hex bytes with an optional 0x prefix, whitespace ignored. */
+pub(crate) fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    let text: String = text.split_whitespace().collect();
+    let text = text.strip_prefix("0x").unwrap_or(&text);
+    if text.len() & 1 != 0 { return None; }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
+
+/* Decimal or hex with a 0x prefix, values out of range of the type are rejected. */
+pub(crate) fn parse_number<T: TryFrom<u64>>(text: &str) -> Option<T> {
+    let value = match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => text.parse().ok()?
+    };
+    T::try_from(value).ok()
+}
+
+/* Text with \r, \n, \t, \\ and \xNN escapes. */
+pub(crate) fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let mut result = Vec::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0u8; 4];
+            result.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('r') => result.push(b'\r'),
+            Some('n') => result.push(b'\n'),
+            Some('t') => result.push(b'\t'),
+            Some('0') => result.push(0),
+            Some('\\') => result.push(b'\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(value) => result.push(value),
+                    Err(_) => return Err("Bad \\x escape in ".to_owned() + text)
+                }
+            },
+            _ => return Err("Bad escape sequence in ".to_owned() + text)
+        }
+    }
+    Ok(result)
+}
+
+/* Inverse of unescape, keeping printable ASCII as is. */
+pub(crate) fn escape(data: &[u8]) -> String {
+    let mut result = String::with_capacity(data.len());
+    for byte in data {
+        match byte {
+            b'\r' => result += "\\r",
+            b'\n' => result += "\\n",
+            b'\t' => result += "\\t",
+            b'\\' => result += "\\\\",
+            0x20..=0x7e => result.push(*byte as char),
+            _ => result += &format!("\\x{:02x}", byte)
+        }
+    }
+    result
+}
+
+/* Entry text as bytes, escapes are only read when the check button is active. */
+pub(crate) fn entry_data(entry: &gtk::Entry, escapes: &gtk::CheckButton) -> Option<Vec<u8>> {
+    if !escapes.is_active() { return Some(entry.text().bytes().collect()); }
+    match unescape(&entry.text()) {
+        Ok(data) => Some(data),
+        Err(what) => { error(&what); None }
+    }
+}
+
+/* Sets an (Auto, entry) pair, None leaves the value to be computed. */
+pub(crate) fn set_auto_field(field: &(gtk::CheckButton, gtk::Entry), value: Option<String>) {
+    field.0.set_active(value.is_none());
+    field.1.set_text(&value.unwrap_or_default());
+}
+
+/* Real interfaces followed by the pcap file and null pseudo-interfaces, the path entry only shows for the first. */
 struct NetworkInterfaceWidget {
     list: gtk::DropDown,
+    path: gtk::Entry,
     interfaces: Vec<String>
 }
 impl NetworkInterfaceWidget {
     fn new(names: &[&str]) -> Self {
-        let list = gtk::DropDown::from_strings(names);
-        let mut interfaces = Vec::with_capacity(names.len());
+        let mut interfaces = Vec::with_capacity(names.len() + 2);
         for name in names { interfaces.push(name.to_string()); }
-        Self { list, interfaces }
+        interfaces.push(channel::PCAP_PREFIX.to_string());
+        interfaces.push(channel::NULL_INTERFACE.to_string());
+
+        let shown: Vec<&str> = interfaces.iter().map(|name| match name.as_str() {
+            channel::PCAP_PREFIX => "pcap file",
+            name => name
+        }).collect();
+        let list = gtk::DropDown::from_strings(&shown);
+        let path = gtk::Entry::builder().placeholder_text("out.pcap").visible(false).build();
+
+        let (pcap, shown_path) = (interfaces.len() as u32 - 2, path.clone());
+        list.connect_selected_notify(move |list| shown_path.set_visible(list.selected() == pcap));
+        Self { list, path, interfaces }
+    }
+    pub(crate) fn get_active(&self) -> String {
+        match self.interfaces[self.list.selected() as usize].as_str() {
+            channel::PCAP_PREFIX => channel::PCAP_PREFIX.to_string() + &self.path.text(),
+            name => name.to_string()
+        }
     }
-    pub(crate) fn get_active(&self) -> String { self.interfaces[self.list.selected() as usize].clone() }
 }
 
 struct MacAddressesWidgets {
@@ -73,19 +183,39 @@ impl MacAddressesWidgets {
 
         Some((source, destination))
     }
+    fn load(&self, frame: &[u8]) {
+        let text = |bytes: &[u8]| bytes.iter().map(|v| format!("{:02x}", v)).collect::<Vec<_>>().join(".");
+        self.destination.set_text(&text(&frame[0..6]));
+        self.source.set_text(&text(&frame[6..12]));
+    }
 }
 
+/* Protocols of the radio buttons, in protocol table order: two per row. */
+#[derive(Clone, Copy, PartialEq)]
+enum Protocol { Ip, Icmp, Tcp, Udp, Igmp, Icmpv6, Sctp }
+const PROTOCOLS: [(Protocol, &str); 7] = [(Protocol::Ip, "IP"), (Protocol::Icmp, "ICMP"), (Protocol::Tcp, "TCP"), (Protocol::Udp, "UDP"),
+    (Protocol::Igmp, "IGMP"), (Protocol::Icmpv6, "ICMPv6"), (Protocol::Sctp, "SCTP")];
+
 pub struct MainWindowWidgets {
     interface_list: NetworkInterfaceWidget,
 
-    buttons: (gtk::CheckButton, gtk::CheckButton, gtk::CheckButton, gtk::CheckButton),
+    buttons: Vec<(Protocol, gtk::CheckButton)>,
     macs: MacAddressesWidgets,
+    ethernet: EthernetWidgets,
 
     pub(crate) ip_widgets: IPWidgets,
-    tcp_widgets: TCPWidgets
+    fragmentation: FragmentationWidgets,
+    pub(crate) tcp_widgets: TCPWidgets,
+    segmentation: SegmentationWidgets,
+    tunnel: TunnelWidgets,
+
+    /* Transport bytes of a loaded packet, picked up by the UDP or ICMP dialog when it opens next. */
+    loaded: Option<(u8, Vec<u8>)>,
+    /* Checked, no raw socket is opened, only the pseudo-interfaces send. */
+    offline: gtk::CheckButton
 }
 impl MainWindowWidgets {
-    fn generate_ui(&self, button: &gtk::Button, database: &gtk::Box) -> gtk::Box {
+    fn generate_ui(&self, buttons: &gtk::Box, database: &gtk::Box) -> gtk::Box {
         let container = gtk::Box::builder().orientation(gtk::Orientation::Vertical).margin_top(24).margin_bottom(24)
             .margin_start(24).margin_end(24).halign(gtk::Align::Center).valign(gtk::Align::Center).spacing(24).build();
 
@@ -96,26 +226,31 @@ impl MainWindowWidgets {
             /* Initialize first section. */
             section_box.append(&gtk::Label::new(Some("Interface:")));
             section_box.append(&self.interface_list.list);
+            section_box.append(&self.interface_list.path);
             section_box.append(&self.get_protocol_table());
             section_box.append(&self.ip_widgets.prepare_address_section());
 
-            /* Add main button. */
-            section_box.append(button);
+            /* Add main buttons. */
+            section_box.append(buttons);
 
             container.append(&section_box);
         }
 
         /* Second section. */
         container.append(&self.get_mac_address_table());
+        container.append(&self.ethernet.prepare_ui_fields());
 
         /* Third section. */
         container.append(database);
 
         /* Forth section. */
         container.append(&self.ip_widgets.prepare_options_section());
+        container.append(&self.fragmentation.prepare_ui_fields());
+        container.append(&self.tunnel.prepare_ui_fields());
 
         /* Fifth section. */
         container.append(&self.tcp_widgets.prepare_ui_fields());
+        container.append(&self.segmentation.prepare_ui_fields());
 
         container
     }
@@ -123,15 +258,10 @@ impl MainWindowWidgets {
         let protocol_table = gtk::Grid::builder().margin_start(6).margin_end(6).row_spacing(6)
             .halign(gtk::Align::Center).valign(gtk::Align::Center).column_spacing(6).name("protocol-table").build();
 
-        /* Ip - 0, tcp - 1, udp - 2, icmp - 3 */
-        self.buttons.1.set_group(Some(&self.buttons.2));
-        self.buttons.3.set_group(Some(&self.buttons.1));
-        self.buttons.0.set_group(Some(&self.buttons.3));
-
-        protocol_table.attach(&self.buttons.0, 0, 0, 1, 1);
-        protocol_table.attach(&self.buttons.3, 1, 0, 1, 1);
-        protocol_table.attach(&self.buttons.1, 0, 1, 1, 1);
-        protocol_table.attach(&self.buttons.2, 1, 1, 1, 1);
+        for (i, (_, button)) in self.buttons.iter().enumerate() {
+            if i > 0 { button.set_group(Some(&self.buttons[0].1)); }
+            protocol_table.attach(button, i as i32 % 2, i as i32 / 2, 1, 1);
+        }
 
         protocol_table
     }
@@ -150,9 +280,67 @@ impl MainWindowWidgets {
         grid
     }
 
+    pub(crate) fn get_macs(&self) -> Option<(MacAddr, MacAddr)> {
+        self.macs.get()
+    }
+    /* Addressing of a client without a lease: 0.0.0.0 to the broadcast address. */
+    pub(crate) fn apply_dhcp_defaults(&self) {
+        self.ip_widgets.set_addresses(Ipv4Addr::UNSPECIFIED, Ipv4Addr::BROADCAST);
+        self.macs.destination.set_text("ff.ff.ff.ff.ff.ff");
+    }
+
+    /* Fills the editor from a stored frame. Lengths and checksums are left on Auto, so that the packet can be
+     * changed and collected again. */
+    pub(crate) fn load_frame(&mut self, frame: &[u8]) {
+        if frame.len() < 14 { error("The frame is too short to load."); return; }
+        self.macs.load(frame);
+        self.ethernet.load(frame);
+        self.loaded = None;
+
+        let ip = match dissect::ipv4_payload(frame) {
+            Some(ip) if ip.len() >= 20 && ip[0] >> 4 == 4 => ip,
+            _ => return
+        };
+        let transport = self.ip_widgets.load(ip);
+        self.select_protocol(match ip[9] {
+            6 => Protocol::Tcp,
+            17 => Protocol::Udp,
+            1 => Protocol::Icmp,
+            2 => Protocol::Igmp,
+            132 => Protocol::Sctp,
+            _ => Protocol::Ip
+        });
+
+        match ip[9] {
+            6 => self.tcp_widgets.load(transport),
+            17 | 1 => self.loaded = Some((ip[9], transport.to_vec())),
+            _ => {}
+        }
+    }
+    fn protocol(&self) -> Protocol {
+        self.buttons.iter().find(|(_, button)| button.is_active()).map(|(protocol, _)| *protocol).unwrap_or(Protocol::Ip)
+    }
+    fn select_protocol(&self, protocol: Protocol) {
+        if let Some((_, button)) = self.buttons.iter().find(|(known, _)| *known == protocol) { button.set_active(true); }
+    }
+    pub(crate) fn take_loaded(&mut self, protocol: u8) -> Option<Vec<u8>> {
+        match self.loaded.take() {
+            Some((loaded, data)) if loaded == protocol => Some(data),
+            other => { self.loaded = other; None }
+        }
+    }
+
     pub(crate) fn get_active_interface(&self) -> String {
         self.interface_list.get_active()
     }
+    pub(crate) fn is_offline(&self) -> bool {
+        self.offline.is_active()
+    }
+    /* Ping, traceroute and TCP sessions stamp plain IPv4 frames out of a template and match the replies
+     * unwrapped, so they are only offered without a tunnel and without fragmentation. */
+    pub(crate) fn plain_ip(&self) -> bool {
+        !self.tunnel.is_enabled() && !self.fragmentation.is_enabled()
+    }
 
     fn new() -> Self {
         let binding = datalink::interfaces();
@@ -162,43 +350,51 @@ impl MainWindowWidgets {
         Self {
             interface_list: NetworkInterfaceWidget::new(&names),
 
-            buttons: ( gtk::CheckButton::builder().label("IP").active(true).build(), gtk::CheckButton::with_label("TCP"),
-                       gtk::CheckButton::with_label("UDP"), gtk::CheckButton::with_label("ICMP") ),
+            buttons: PROTOCOLS.iter().map(|(protocol, label)| {
+                (*protocol, gtk::CheckButton::builder().label(label).active(*protocol == Protocol::Ip).build())
+            }).collect(),
 
             macs: MacAddressesWidgets::new(source_mac),
+            ethernet: EthernetWidgets::new(),
             ip_widgets: IPWidgets::new(),
-            tcp_widgets: TCPWidgets::new()
+            fragmentation: FragmentationWidgets::new(),
+            tcp_widgets: TCPWidgets::new(),
+            segmentation: SegmentationWidgets::new(),
+            tunnel: TunnelWidgets::new(),
+
+            loaded: None,
+            offline: gtk::CheckButton::with_label("Offline")
         }
     }
-    fn build_packet(widgets: Rc<RefCell<MainWindowWidgets>>, database: Rc<RefCell<Database>>) {
-        if widgets.borrow().buttons.3.is_active() {
-            Self::build_icmp_packet(widgets.clone(), database.clone());
+    fn build_packet(widgets: Rc<RefCell<MainWindowWidgets>>, database: Rc<RefCell<Database>>, send_now: bool) {
+        if widgets.borrow().ethernet.is_raw() {
+            Self::build_raw_frame(widgets, database, send_now);
+            return;
         }
-        if widgets.borrow().buttons.2.is_active() {
-            Self::build_udp_packet(widgets.clone(), database.clone());
-        }
-        if widgets.borrow().buttons.1.is_active() {
-            Self::build_tcp_packet(widgets.clone(), database.clone());
-        }
-        if widgets.borrow().buttons.0.is_active() {
-            match widgets.clone().borrow().tcp_widgets.give_payload() {
-                Some(value) => Self::build_ip_packet(widgets, value, IpNextHeaderProtocol::new(0), database, "IP"),
-                None => Self::build_ip_packet(widgets, Vec::new(), IpNextHeaderProtocol::new(0), database, "IP")
+        let protocol = widgets.borrow().protocol();
+        match protocol {
+            Protocol::Sctp => SctpOptions::show_window(widgets, database, send_now),
+            Protocol::Icmpv6 => Icmpv6Options::show_window(widgets, database, send_now),
+            Protocol::Igmp => IgmpOptions::show_window(widgets, database, send_now),
+            Protocol::Icmp => Self::build_icmp_packet(widgets, database, send_now),
+            Protocol::Udp => Self::build_udp_packet(widgets, database, send_now),
+            Protocol::Tcp => Self::build_tcp_packet(widgets, database, send_now),
+            Protocol::Ip => {
+                let payload = match widgets.borrow().tcp_widgets.give_payload() {
+                    Some(payload) => payload,
+                    None => return
+                };
+                Self::build_ip_packet(widgets, payload, IpNextHeaderProtocol::new(0), database, "IP", send_now);
             }
         }
     }
-    fn build_icmp_packet(widgets: Rc<RefCell<MainWindowWidgets>>, database: Rc<RefCell<Database>>) {
-        IcmpOptions::show_window(widgets, database);
+    fn build_icmp_packet(widgets: Rc<RefCell<MainWindowWidgets>>, database: Rc<RefCell<Database>>, send_now: bool) {
+        IcmpOptions::show_window(widgets, database, send_now);
     }
-    fn build_udp_packet(widgets: Rc<RefCell<MainWindowWidgets>>, database: Rc<RefCell<Database>>) {
-        let addresses = match widgets.clone().borrow().ip_widgets.get_addresses() {
-            Some(addresses) => addresses,
-            None => { error("Bad src or destination IP address value."); return }
-        };
-
-        UdpOptions::show_window(widgets.clone(), addresses, database);
+    fn build_udp_packet(widgets: Rc<RefCell<MainWindowWidgets>>, database: Rc<RefCell<Database>>, send_now: bool) {
+        UdpOptions::show_window(widgets.clone(), database, send_now);
     }
-    fn build_tcp_packet(widgets: Rc<RefCell<MainWindowWidgets>>, database: Rc<RefCell<Database>>) {
+    fn build_tcp_packet(widgets: Rc<RefCell<MainWindowWidgets>>, database: Rc<RefCell<Database>>, send_now: bool) {
         let addresses = match widgets.borrow().ip_widgets.get_addresses() {
             Some(addresses) => addresses,
             None => { error("Bad src or destination IP address value."); return }
@@ -208,54 +404,109 @@ impl MainWindowWidgets {
             Some(packet) => packet,
             None => { return }
         };
-        show("TCP packet", &packet);
-        Self::build_ip_packet(widgets, packet, IpNextHeaderProtocol::new(6), database, "TCP");
+
+        let checksum = widgets.borrow().tcp_widgets.auto_checksum();
+        let segments = match widgets.borrow().segmentation.segment(&packet, addresses, checksum) {
+            Some(segments) => segments,
+            None => { return }
+        };
+        match segments.len() {
+            1 => Self::build_ip_packet(widgets, packet, IpNextHeaderProtocol::new(6), database, "TCP", send_now),
+            _ => for (i, segment) in segments.into_iter().enumerate() {
+                Self::build_ip_packet(widgets.clone(), segment, IpNextHeaderProtocol::new(6), database.clone(), &format!("TCP seg {}", i), send_now);
+            }
+        }
     }
-    fn build_ip_packet(widgets: Rc<RefCell<MainWindowWidgets>>, data: Vec<u8>, next_protocol: IpNextHeaderProtocol, database: Rc<RefCell<Database>>, label: &str) {
+    pub(crate) fn build_ip_packet(widgets: Rc<RefCell<MainWindowWidgets>>, data: Vec<u8>, next_protocol: IpNextHeaderProtocol, database: Rc<RefCell<Database>>, label: &str, send_now: bool) {
         let packet = match widgets.borrow().ip_widgets.build_packet(next_protocol, &data) {
             Some(packet) => packet,
             None => { return }
         };
-        Self::build_frame(widgets.clone(), &packet, database, label);
+        let packet = match widgets.borrow().encapsulate(packet) {
+            Some(packet) => packet,
+            None => { return }
+        };
+        Self::send_ip_packet(widgets, packet, database, label, send_now);
+    }
+    /* Complete IPv6 packets skip the IP section, but still go through the tunnel when one is enabled. */
+    pub(crate) fn send_ipv6_packet(widgets: Rc<RefCell<MainWindowWidgets>>, packet: Vec<u8>, database: Rc<RefCell<Database>>, label: &str, send_now: bool) {
+        if !widgets.borrow().tunnel.is_enabled() {
+            Self::build_frame(widgets, &packet, database, label, send_now);
+            return;
+        }
+        let packet = match widgets.borrow().encapsulate(packet) {
+            Some(packet) => packet,
+            None => { return }
+        };
+        Self::send_ip_packet(widgets, packet, database, label, send_now);
+    }
+    /* Wraps an IP packet into the configured tunnel, into an inner Ethernet frame first if the tunnel needs one. */
+    fn encapsulate(&self, packet: Vec<u8>) -> Option<Vec<u8>> {
+        if !self.tunnel.is_enabled() { return Some(packet); }
+        let inner = match self.tunnel.carries_frame() {
+            true => self.wrap_frame(&packet)?,
+            false => packet
+        };
+        self.tunnel.encapsulate(&self.ip_widgets, &inner)
     }
-    pub(crate) fn build_frame(widgets: Rc<RefCell<MainWindowWidgets>>, data: &Vec<u8>, database: Rc<RefCell<Database>>, label: &str) {
-        let mut frame = MutableEthernetPacket::owned(vec![0u8; MutableEthernetPacket::minimum_packet_size() + data.len()]).unwrap();
+    fn send_ip_packet(widgets: Rc<RefCell<MainWindowWidgets>>, packet: Vec<u8>, database: Rc<RefCell<Database>>, label: &str, send_now: bool) {
+        let fragments = match widgets.borrow().fragmentation.fragment(&packet) {
+            Some(fragments) => fragments,
+            None => { return }
+        };
 
-        match widgets.borrow().macs.get() {
-            Some(addresses) => { frame.set_source(addresses.0); frame.set_destination(addresses.1); },
+        match fragments.len() {
+            1 => Self::build_frame(widgets.clone(), &fragments[0], database, label, send_now),
+            _ => for (i, fragment) in fragments.iter().enumerate() {
+                Self::build_frame(widgets.clone(), fragment, database.clone(), &format!("{} frag {}", label, i), send_now);
+            }
+        }
+    }
+    pub(crate) fn wrap_frame(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let mut addresses = self.macs.get()?;
+        if let Some(multicast) = self.ethernet.multicast_mac(data) { addresses.1 = multicast; }
+        let ethertype = match data.first().map(|v| v >> 4) {
+            Some(6) => 0x86dd,
+            _ => 0x0800
+        };
+        let mut frame = self.ethernet.ip_header(addresses, ethertype)?;
+        frame.extend_from_slice(data);
+        Some(frame)
+    }
+    pub(crate) fn build_frame(widgets: Rc<RefCell<MainWindowWidgets>>, data: &Vec<u8>, database: Rc<RefCell<Database>>, label: &str, send_now: bool) {
+        let payload = match widgets.borrow().wrap_frame(data) {
+            Some(payload) => payload,
+            None => return
+        };
+        Self::store_frame(widgets, payload, database, label, send_now);
+    }
+    fn build_raw_frame(widgets: Rc<RefCell<MainWindowWidgets>>, database: Rc<RefCell<Database>>, send_now: bool) {
+        let frame = match widgets.borrow().macs.get() {
+            Some(addresses) => widgets.borrow().ethernet.raw_frame(addresses),
+            None => return
+        };
+        let frame = match frame {
+            Some(frame) => frame,
             None => return
+        };
+        if !widgets.borrow().tunnel.is_enabled() {
+            Self::store_frame(widgets, frame, database, "Raw", send_now);
+            return;
         }
 
-        frame.set_ethertype(EtherType::new(0x0800));
-        frame.set_payload(data);
-
+        if !widgets.borrow().tunnel.carries_frame() { error("This tunnel can only carry IP packets"); return; }
+        let packet = match widgets.borrow().tunnel.encapsulate(&widgets.borrow().ip_widgets, &frame) {
+            Some(packet) => packet,
+            None => return
+        };
+        Self::send_ip_packet(widgets, packet, database, "Raw tunnel", send_now);
+    }
+    fn store_frame(widgets: Rc<RefCell<MainWindowWidgets>>, payload: Vec<u8>, database: Rc<RefCell<Database>>, label: &str, send_now: bool) {
         let interface = widgets.borrow().interface_list.get_active();
+        let offline = widgets.borrow().is_offline();
 
-        let payload = Vec::from(frame.packet());
-        show("Ethernet frame", &payload);
         database.borrow_mut().push(payload.clone(), label);
-        Self::send_frame(&payload, &interface);
-    }
-
-    fn send_frame(payload: &Vec<u8>, iface: &str) {
-        let interfaces = datalink::interfaces();
-        let interface = interfaces.into_iter()
-            .filter(|interface: &NetworkInterface| {
-                interface.name == iface
-            })
-            .next()
-            .unwrap();
-
-        let (mut tx, _) = match datalink::channel(&interface, Default::default()) {
-            Ok(Ethernet(tx, rx)) => (tx, rx),
-            Ok(_) => panic!("Unhandled channel type."),
-            Err(_) => panic!("Failed to create datalink channel."),
-        };
-
-        match tx.send_to(&payload, None) {
-            Some(_) => {},
-            None => { error("Failed to send packet."); return }
-        }
+        if send_now { database.borrow().send_frame(&payload, &interface, offline); }
     }
 }
 
@@ -270,13 +521,47 @@ impl MainWindow {
 
         let database_ui = Database::get_ui_section(widgets.clone(), database.clone());
 
-        let button = gtk::Button::with_label("Collect");
-        let ui = widgets.borrow().generate_ui(&button, &database_ui);
+        let queue = gtk::Button::with_label("Add to queue");
+        let send = gtk::Button::with_label("Send now");
+        let traceroute = gtk::Button::with_label("Traceroute");
+        let session = gtk::Button::with_label("TCP Session");
+        let replay = gtk::Button::with_label("Replay pcap");
+        let buttons = gtk::Box::builder().orientation(gtk::Orientation::Vertical).valign(gtk::Align::Center).spacing(6).build();
+        buttons.append(&queue); buttons.append(&send); buttons.append(&traceroute); buttons.append(&session);
+        buttons.append(&replay);
+        buttons.append(&widgets.borrow().offline);
+        let ui = widgets.borrow().generate_ui(&buttons, &database_ui);
+
+        for (button, send_now) in [(&queue, false), (&send, true)] {
+            let clone = widgets.clone();
+            let database_clone = database.clone();
+            button.connect_clicked(move |_| {
+                MainWindowWidgets::build_packet(clone.clone(), database_clone.clone(), send_now);
+            });
+        }
+
+        for check in [widgets.borrow().tunnel.enabled_button(), widgets.borrow().fragmentation.enabled_button()] {
+            let (clone, traceroute, session) = (widgets.clone(), traceroute.clone(), session.clone());
+            check.connect_toggled(move |_| {
+                let plain = clone.borrow().plain_ip();
+                traceroute.set_sensitive(plain);
+                session.set_sensitive(plain);
+            });
+        }
+
+        let clone = widgets.clone();
+        traceroute.connect_clicked(move |_| {
+            TracerouteOptions::show_window(clone.clone());
+        });
+
+        let clone = widgets.clone();
+        session.connect_clicked(move |_| {
+            SessionOptions::show_window(clone.clone());
+        });
 
         let clone = widgets.clone();
-        let database_clone = database.clone();
-        button.connect_clicked(move |_| {
-            MainWindowWidgets::build_packet(clone.clone(), database_clone.clone());
+        replay.connect_clicked(move |_| {
+            ReplayOptions::show_window(clone.clone(), database.clone());
         });
 
         let window = gtk::ApplicationWindow::builder()